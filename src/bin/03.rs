@@ -1,128 +1,487 @@
-use std::path::Path;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
 
-type BitVec = Vec<bool>;
-
-trait Bits {
-    fn width(&self) -> usize;
-    fn to_decimal(&self) -> usize;
-    fn from_str(s: &str) -> Self;
-    fn invert(&self) -> Self;
-    fn to_string(&self) -> String;
+/// Bits packed into `u64` words, index 0 being the most significant bit.
+#[derive(Clone, Debug, PartialEq)]
+struct BitVec {
+    words: Vec<u64>,
+    len: usize,
 }
 
-impl Bits for BitVec {
-    fn width(&self) -> usize {
-        self.len()
+impl BitVec {
+    fn zeros(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
     }
 
-    fn to_decimal(&self) -> usize {
-        self.iter()
-            .rev()
-            .enumerate()
-            .map(|(idx, set)| match set {
-                true => 2usize.pow(idx as u32),
-                false => 0,
-            })
-            .sum()
+    fn ones(len: usize) -> Self {
+        Self::zeros(len).invert()
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & 1 << (i % 64) != 0
+    }
+
+    fn set(&mut self, i: usize, bit: bool) {
+        match bit {
+            true => self.words[i / 64] |= 1 << (i % 64),
+            false => self.words[i / 64] &= !(1 << (i % 64)),
+        }
     }
 
     fn from_str(s: &str) -> Self {
-        s.chars()
-            .map(|c| match c {
-                '1' => true,
-                '0' => false,
-                x => panic!("invalid char: {} in binary string", x),
-            })
-            .collect()
+        let mut bits = Self::zeros(s.len());
+        s.chars().enumerate().for_each(|(i, c)| match c {
+            '1' => bits.set(i, true),
+            '0' => (),
+            x => panic!("invalid char: {} in binary string", x),
+        });
+        bits
+    }
+
+    /// Clears the unused bits of the last word.
+    fn trim(mut self) -> Self {
+        if !self.len.is_multiple_of(64) {
+            *self.words.last_mut().unwrap() &= (1 << (self.len % 64)) - 1;
+        }
+        self
     }
 
     fn invert(&self) -> Self {
-        self.iter().map(|x| !x).collect()
+        Self {
+            words: self.words.iter().map(|w| !w).collect(),
+            len: self.len,
+        }
+        .trim()
+    }
+
+    fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Number of bits set in both `self` and `other`.
+    fn and_count(&self, other: &Self) -> usize {
+        self.words
+            .iter()
+            .zip(other.words.iter())
+            .map(|(a, b)| (a & b).count_ones() as usize)
+            .sum()
+    }
+
+    fn and_assign(&mut self, other: &Self) {
+        self.words
+            .iter_mut()
+            .zip(other.words.iter())
+            .for_each(|(a, b)| *a &= b);
+    }
+
+    fn and_not_assign(&mut self, other: &Self) {
+        self.words
+            .iter_mut()
+            .zip(other.words.iter())
+            .for_each(|(a, b)| *a &= !b);
+    }
+
+    fn first_one(&self) -> Option<usize> {
+        self.words
+            .iter()
+            .position(|w| *w != 0)
+            .map(|i| i * 64 + self.words[i].trailing_zeros() as usize)
+    }
+
+    /// Value as little endian `u64` limbs.
+    fn to_limbs(&self) -> Vec<u64> {
+        let mut limbs = vec![0; self.words.len()];
+        (0..self.len).filter(|i| self.get(*i)).for_each(|i| {
+            let weight = self.len - 1 - i;
+            limbs[weight / 64] |= 1 << (weight % 64);
+        });
+        limbs
+    }
+
+    /// Value if it fits in a `u64`.
+    fn to_u64(&self) -> Option<u64> {
+        let limbs = self.to_limbs();
+        match limbs.iter().skip(1).all(|limb| *limb == 0) {
+            true => Some(limbs.first().copied().unwrap_or(0)),
+            false => None,
+        }
+    }
+
+    /// Value in base 10, for any width.
+    #[allow(dead_code)]
+    fn to_decimal(&self) -> String {
+        const CHUNK: u64 = 10_000_000_000_000_000_000;
+        let mut limbs = self.to_limbs();
+        let mut chunks = Vec::new();
+        while limbs.iter().any(|limb| *limb != 0) {
+            let mut remainder = 0u128;
+            for limb in limbs.iter_mut().rev() {
+                let current = remainder << 64 | *limb as u128;
+                *limb = (current / CHUNK as u128) as u64;
+                remainder = current % CHUNK as u128;
+            }
+            chunks.push(remainder as u64);
+        }
+        match chunks.split_last() {
+            None => "0".to_owned(),
+            Some((most, rest)) => rest
+                .iter()
+                .rev()
+                .fold(most.to_string(), |s, chunk| s + &format!("{:019}", chunk)),
+        }
     }
+}
 
-    fn to_string(&self) -> String {
-        self.iter()
-            .map(|bit| match bit {
+impl Display for BitVec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = (0..self.len)
+            .map(|i| match self.get(i) {
                 true => '1',
                 false => '0',
             })
+            .collect::<String>();
+        write!(f, "{}", s)
+    }
+}
+
+/// Diagnostic report stored column wise, so counting a bit position over all
+/// records is a popcount over words.
+#[derive(Clone, Debug)]
+struct Report {
+    records: usize,
+    /// `columns[i]` holds bit `i` of every record
+    columns: Vec<BitVec>,
+}
+
+impl Report {
+    fn width(&self) -> usize {
+        self.columns.len()
+    }
+
+    /// Records whose bit is set in `alive`.
+    #[allow(dead_code)]
+    fn records_in(&self, alive: &BitVec) -> Vec<BitVec> {
+        (0..self.records)
+            .filter(|r| alive.get(*r))
+            .map(|r| self.record(r))
             .collect()
     }
+
+    fn record(&self, r: usize) -> BitVec {
+        let mut bits = BitVec::zeros(self.width());
+        self.columns
+            .iter()
+            .enumerate()
+            .for_each(|(i, column)| bits.set(i, column.get(r)));
+        bits
+    }
 }
 
 fn main() {
-    let diagnostics = get_report("input/03.txt");
+    // cargo run --bin 03 -- trace [file]
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("trace") {
+        let diagnostics = get_report(args.get(2).map_or("input/03.txt", String::as_str));
+        println!("gamma: {}", calculate_gamma_rate(&diagnostics));
+        for (name, criteria) in [("oxygen", BitCriteria::OXYGEN), ("co2", BitCriteria::CO2)] {
+            for step in filter_trace(&diagnostics, &criteria) {
+                println!(
+                    "{} bit {} = {}: {} left",
+                    name,
+                    step.bit_pos,
+                    step.selected as u8,
+                    step.alive.count_ones()
+                );
+            }
+            println!("{}: {}", name, get_rating_value(&diagnostics, &criteria));
+        }
+        return;
+    }
 
-    let gamma_rate = calculate_gamma_rate(&diagnostics);
-    let epsilon_rate = gamma_rate.invert();
-    let o2_rate = get_rating_value(&diagnostics, true);
-    let co2_rate = get_rating_value(&diagnostics, false);
+    let file = || BufReader::new(File::open("input/03.txt").unwrap());
+    let counter = BitCounter::from_reader(file());
+    let gamma_rate = counter.gamma_rate();
+    let epsilon_rate = counter.epsilon_rate();
+    let trie = BitTrie::from_reader(file());
+    let o2_rate = trie.rating(&BitCriteria::OXYGEN);
+    let co2_rate = trie.rating(&BitCriteria::CO2);
 
     println!(
         "part 1: {}",
-        gamma_rate.to_decimal() * epsilon_rate.to_decimal()
+        gamma_rate.to_u64().unwrap() * epsilon_rate.to_u64().unwrap()
+    );
+    println!(
+        "part 2: {}",
+        o2_rate.to_u64().unwrap() * co2_rate.to_u64().unwrap()
     );
-    println!("part 2: {}", o2_rate.to_decimal() * co2_rate.to_decimal());
 }
 
-fn get_report(p: impl AsRef<Path>) -> Vec<BitVec> {
-    let text = std::fs::read_to_string(p.as_ref()).unwrap();
-    text.lines().map(|line| Bits::from_str(line)).collect()
+fn get_report(p: impl AsRef<Path>) -> Report {
+    parse_report(&std::fs::read_to_string(p.as_ref()).unwrap())
+}
+
+fn parse_report(text: &str) -> Report {
+    let width = text.lines().next().map_or(0, str::len);
+    let records = text.lines().count();
+    let mut columns = vec![BitVec::zeros(records); width];
+    text.lines().enumerate().for_each(|(r, line)| {
+        let record = BitVec::from_str(line);
+        (0..width)
+            .filter(|i| record.get(*i))
+            .for_each(|i| columns[i].set(r, true));
+    });
+    Report { records, columns }
 }
 
 #[allow(dead_code)]
 fn print_bitvec(b: &[BitVec]) {
     println!("-----------------");
-    b.iter().for_each(|v| println!("{}", v.to_string()));
+    b.iter().for_each(|v| println!("{}", v));
     println!("-----------------");
 }
 
-fn calculate_gamma_rate(diagnostics: &[BitVec]) -> BitVec {
-    let bits_width = diagnostics[0].width();
-    let n_vecs_half = diagnostics.len() as f64 / 2.0f64;
-    (0..bits_width)
-        .into_iter()
-        .map(|i| {
-            diagnostics
-                .iter()
-                .filter_map(|d| match d[i] {
-                    true => Some(()),
-                    _ => None,
-                })
-                .count() as f64
-                > n_vecs_half
-        })
-        .collect()
-}
-
-fn filter_records(diagnostics: &mut Vec<BitVec>, most_common: bool, bit_pos: usize) {
-    let n_vecs_half = diagnostics.len() as f64 / 2.0f64;
-    let set_bit_freq = diagnostics
+fn calculate_gamma_rate(diagnostics: &Report) -> BitVec {
+    let mut gamma = BitVec::zeros(diagnostics.width());
+    diagnostics
+        .columns
         .iter()
-        .filter_map(|d| match d[bit_pos] {
-            true => Some(()),
-            _ => None,
-        })
-        .count() as f64;
-
-    let select_bit = match most_common {
-        true => set_bit_freq >= n_vecs_half,
-        false => set_bit_freq < n_vecs_half,
+        .enumerate()
+        .for_each(|(i, column)| gamma.set(i, column.count_ones() * 2 > diagnostics.records));
+    gamma
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Commonness {
+    Most,
+    Least,
+}
+
+/// Order in which bit positions are examined.
+#[allow(dead_code)]
+#[derive(Clone, Debug, PartialEq)]
+enum BitOrder {
+    MostSignificantFirst,
+    LeastSignificantFirst,
+    Custom(Vec<usize>),
+}
+
+impl BitOrder {
+    fn positions(&self, width: usize) -> Vec<usize> {
+        match self {
+            Self::MostSignificantFirst => (0..width).collect(),
+            Self::LeastSignificantFirst => (0..width).rev().collect(),
+            Self::Custom(positions) => positions.clone(),
+        }
+    }
+}
+
+/// Which records survive each round of filtering: the ones with the most (or least)
+/// common bit at the examined position, or the ones with `tie` if both are as common.
+#[derive(Clone, Debug, PartialEq)]
+struct BitCriteria {
+    keep: Commonness,
+    tie: bool,
+    order: BitOrder,
+}
+
+impl BitCriteria {
+    const OXYGEN: Self = Self {
+        keep: Commonness::Most,
+        tie: true,
+        order: BitOrder::MostSignificantFirst,
     };
 
-    diagnostics.retain(|v| v[bit_pos] == select_bit);
+    const CO2: Self = Self {
+        keep: Commonness::Least,
+        tie: false,
+        order: BitOrder::MostSignificantFirst,
+    };
+
+    fn select_bit(&self, set_bit_freq: usize, n_vecs: usize) -> bool {
+        match (set_bit_freq * 2).cmp(&n_vecs) {
+            std::cmp::Ordering::Equal => self.tie,
+            std::cmp::Ordering::Greater => self.keep == Commonness::Most,
+            std::cmp::Ordering::Less => self.keep == Commonness::Least,
+        }
+    }
 }
 
-fn get_rating_value(diagnostics: &[BitVec], o2: bool) -> BitVec {
-    let bits_width = diagnostics[0].width();
-    let mut dr = diagnostics.to_owned();
-    for i in 0..bits_width {
-        filter_records(&mut dr, o2, i);
-        if dr.len() == 1 {
+/// Records still alive after filtering on a bit position.
+#[derive(Clone, Debug)]
+struct FilterStep {
+    bit_pos: usize,
+    selected: bool,
+    alive: BitVec,
+}
+
+/// Keeps the records in `alive` whose bit at `bit_pos` meets the criteria, returns the bit kept.
+/// A bit none of the records in `alive` have is never kept.
+fn filter_records(
+    diagnostics: &Report,
+    alive: &mut BitVec,
+    criteria: &BitCriteria,
+    bit_pos: usize,
+) -> bool {
+    let column = &diagnostics.columns[bit_pos];
+    let set_bit_freq = column.and_count(alive);
+    let n_vecs = alive.count_ones();
+
+    let select_bit = match set_bit_freq {
+        0 => false,
+        f if f == n_vecs => true,
+        _ => criteria.select_bit(set_bit_freq, n_vecs),
+    };
+    match select_bit {
+        true => alive.and_assign(column),
+        false => alive.and_not_assign(column),
+    }
+    select_bit
+}
+
+/// Filters until a single record is left or there are no bit positions left to examine.
+fn filter_trace(diagnostics: &Report, criteria: &BitCriteria) -> Vec<FilterStep> {
+    let mut alive = BitVec::ones(diagnostics.records);
+    let mut trace = Vec::new();
+    for bit_pos in criteria.order.positions(diagnostics.width()) {
+        if alive.count_ones() <= 1 {
             break;
         }
+        let selected = filter_records(diagnostics, &mut alive, criteria, bit_pos);
+        trace.push(FilterStep {
+            bit_pos,
+            selected,
+            alive: alive.clone(),
+        });
+    }
+    trace
+}
+
+fn get_rating_value(diagnostics: &Report, criteria: &BitCriteria) -> BitVec {
+    let alive = filter_trace(diagnostics, criteria)
+        .pop()
+        .map_or_else(|| BitVec::ones(diagnostics.records), |step| step.alive);
+    diagnostics.record(alive.first_one().unwrap())
+}
+
+/// Number of set bits at each position, counted one line at a time.
+#[derive(Clone, Debug, Default)]
+struct BitCounter {
+    records: usize,
+    ones: Vec<usize>,
+}
+
+impl BitCounter {
+    fn from_reader(mut reader: impl BufRead) -> Self {
+        let mut counter = Self::default();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            counter.add(line.trim_end());
+            line.clear();
+        }
+        counter
+    }
+
+    fn add(&mut self, record: &str) {
+        if record.len() > self.ones.len() {
+            self.ones.resize(record.len(), 0);
+        }
+        record.chars().enumerate().for_each(|(i, c)| match c {
+            '1' => self.ones[i] += 1,
+            '0' => (),
+            x => panic!("invalid char: {} in binary string", x),
+        });
+        self.records += 1;
+    }
+
+    fn gamma_rate(&self) -> BitVec {
+        let mut gamma = BitVec::zeros(self.ones.len());
+        self.ones
+            .iter()
+            .enumerate()
+            .for_each(|(i, ones)| gamma.set(i, ones * 2 > self.records));
+        gamma
+    }
+
+    fn epsilon_rate(&self) -> BitVec {
+        self.gamma_rate().invert()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct TrieNode {
+    /// records passing through this node
+    count: usize,
+    children: [Option<usize>; 2],
+}
+
+/// Records merged on common prefixes, so repeated records and shared prefixes cost
+/// nothing extra. It still grows with the number of distinct records, up to one node
+/// per bit of every record when they have little in common.
+#[derive(Clone, Debug)]
+struct BitTrie {
+    width: usize,
+    nodes: Vec<TrieNode>,
+}
+
+impl BitTrie {
+    fn from_reader(mut reader: impl BufRead) -> Self {
+        let mut trie = Self {
+            width: 0,
+            nodes: vec![TrieNode::default()],
+        };
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 0 {
+            trie.insert(&BitVec::from_str(line.trim_end()));
+            line.clear();
+        }
+        trie
+    }
+
+    fn insert(&mut self, record: &BitVec) {
+        self.width = self.width.max(record.len);
+        let mut node = 0;
+        self.nodes[node].count += 1;
+        for i in 0..record.len {
+            let bit = record.get(i) as usize;
+            node = match self.nodes[node].children[bit] {
+                Some(child) => child,
+                None => {
+                    self.nodes.push(TrieNode::default());
+                    self.nodes[node].children[bit] = Some(self.nodes.len() - 1);
+                    self.nodes.len() - 1
+                }
+            };
+            self.nodes[node].count += 1;
+        }
+    }
+
+    /// Walks down from the most significant bit, taking the branch the criteria selects
+    /// or the only branch left once a single record remains. Only supports criteria
+    /// examining the most significant bit first.
+    fn rating(&self, criteria: &BitCriteria) -> BitVec {
+        assert_eq!(criteria.order, BitOrder::MostSignificantFirst);
+        let count = |child: Option<usize>| child.map_or(0, |c| self.nodes[c].count);
+        let mut rating = BitVec::zeros(self.width);
+        let mut node = self.nodes[0];
+        for i in 0..self.width {
+            let [zeros, ones] = node.children.map(count);
+            let bit = match (zeros, ones) {
+                (0, _) => true,
+                (_, 0) => false,
+                _ => criteria.select_bit(ones, zeros + ones),
+            };
+            rating.set(i, bit);
+            node = self.nodes[node.children[bit as usize].unwrap()];
+        }
+        rating
     }
-    dr[0].to_owned()
 }
 
 #[cfg(test)]
@@ -131,8 +490,15 @@ mod problem03 {
 
     #[test]
     fn to_decimal() {
-        let bitvec: BitVec = Bits::from_str("10110");
-        assert_eq!(bitvec.to_decimal(), 22);
+        let bitvec = BitVec::from_str("10110");
+        assert_eq!(bitvec.to_u64(), Some(22));
+        assert_eq!(bitvec.to_decimal(), "22");
+        assert_eq!(bitvec.to_string(), "10110");
+
+        let wide = BitVec::from_str(&format!("1{}", "0".repeat(100)));
+        assert_eq!(wide.to_u64(), None);
+        assert_eq!(wide.to_decimal(), "1267650600228229401496703205376");
+        assert_eq!(wide.invert().count_ones(), 100);
     }
 
     #[test]
@@ -140,16 +506,79 @@ mod problem03 {
         let diagnostics = get_report("input/03.test.txt");
         let gamma_rate = calculate_gamma_rate(&diagnostics);
         let epsilon_rate = gamma_rate.invert();
-        assert_eq!(gamma_rate.to_decimal(), 22);
-        assert_eq!(epsilon_rate.to_decimal(), 9);
+        assert_eq!(gamma_rate.to_u64(), Some(22));
+        assert_eq!(epsilon_rate.to_u64(), Some(9));
     }
 
     #[test]
     fn part2() {
         let diagnostics = get_report("input/03.test.txt");
-        let o2_rate = get_rating_value(&diagnostics, true);
-        let co2_rate = get_rating_value(&diagnostics, false);
-        assert_eq!(o2_rate.to_decimal(), 23);
-        assert_eq!(co2_rate.to_decimal(), 10);
+        let o2_rate = get_rating_value(&diagnostics, &BitCriteria::OXYGEN);
+        let co2_rate = get_rating_value(&diagnostics, &BitCriteria::CO2);
+        assert_eq!(o2_rate.to_u64(), Some(23));
+        assert_eq!(co2_rate.to_u64(), Some(10));
+    }
+
+    #[test]
+    fn bit_criteria() {
+        let diagnostics = get_report("input/03.test.txt");
+        let trace = filter_trace(&diagnostics, &BitCriteria::OXYGEN);
+        let survivors = trace
+            .iter()
+            .map(|step| diagnostics.records_in(&step.alive).len())
+            .collect::<Vec<_>>();
+        assert_eq!(survivors, vec![7, 4, 3, 2, 1]);
+        assert!(trace[0].selected);
+        assert_eq!(
+            diagnostics.records_in(&trace[2].alive),
+            ["10110", "10111", "10101"].map(BitVec::from_str)
+        );
+
+        // least common ones first, ties keep the ones
+        let criteria = BitCriteria {
+            keep: Commonness::Least,
+            tie: true,
+            order: BitOrder::LeastSignificantFirst,
+        };
+        let trace = filter_trace(&diagnostics, &criteria);
+        assert_eq!(trace[0].bit_pos, 4);
+        assert!(trace[0].selected);
+        assert_eq!(
+            get_rating_value(&diagnostics, &criteria).to_string(),
+            "10101"
+        );
+
+        let criteria = BitCriteria {
+            order: BitOrder::Custom(vec![2, 0]),
+            ..BitCriteria::OXYGEN
+        };
+        assert_eq!(filter_trace(&diagnostics, &criteria).len(), 2);
+    }
+
+    #[test]
+    fn streaming() {
+        let file = || std::io::BufReader::new(std::fs::File::open("input/03.test.txt").unwrap());
+        let counter = BitCounter::from_reader(file());
+        assert_eq!(counter.gamma_rate().to_u64(), Some(22));
+        assert_eq!(counter.epsilon_rate().to_u64(), Some(9));
+
+        let trie = BitTrie::from_reader(file());
+        assert_eq!(trie.rating(&BitCriteria::OXYGEN).to_u64(), Some(23));
+        assert_eq!(trie.rating(&BitCriteria::CO2).to_u64(), Some(10));
+
+        // repeated records share their nodes
+        let trie = BitTrie::from_reader("101\n101\n101\n100\n".as_bytes());
+        assert_eq!(trie.nodes.len(), 5);
+        assert_eq!(trie.rating(&BitCriteria::CO2).to_string(), "100");
+
+        // all records agree, so the least common bit is the only one there is
+        let text = "00\n00\n";
+        let trie = BitTrie::from_reader(text.as_bytes());
+        assert_eq!(trie.rating(&BitCriteria::CO2).to_string(), "00");
+        let diagnostics = parse_report(text);
+        assert_eq!(
+            get_rating_value(&diagnostics, &BitCriteria::CO2).to_string(),
+            "00"
+        );
     }
 }
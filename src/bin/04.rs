@@ -1,5 +1,5 @@
 use itertools::Itertools;
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 
 #[derive(Clone, Copy, Debug, Default)]
 struct BoardCell {
@@ -16,49 +16,318 @@ impl BoardCell {
     }
 }
 
-type Board = [[BoardCell; 5]; 5];
+/// Cells of a `rows` x `cols` board in row major order.
+#[derive(Clone, Debug)]
+struct Board {
+    rows: usize,
+    cols: usize,
+    cells: Vec<BoardCell>,
+}
+
 type DrawNumbers = Vec<usize>;
 
-trait Bingo {
-    fn draw(&mut self, number: usize);
-    fn won(&self) -> bool;
-    fn unmarked_sum(&self) -> usize;
+/// Ways to win, each made up of lines: sets of cells that win once all of them are marked.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+enum WinRule {
+    Rows,
+    Columns,
+    /// both diagonals, square boards only
+    Diagonals,
+    FourCorners,
+    Blackout,
+    /// cells set in a `rows` x `cols` mask
+    Pattern(Vec<Vec<bool>>),
+    Any(Vec<WinRule>),
 }
 
-impl Bingo for Board {
-    fn draw(&mut self, number: usize) {
-        self.iter_mut().flatten().for_each(|cell| {
-            if cell.value == number {
-                cell.marked = true;
-            }
-        });
+impl WinRule {
+    fn standard() -> Self {
+        Self::Any(vec![Self::Rows, Self::Columns])
     }
-    fn won(&self) -> bool {
-        let any_row = self.iter().any(|row| row.iter().all(|cell| cell.marked));
-        let any_col = (0..5)
-            .into_iter()
-            .any(|idx| self.iter().map(|row| row[idx]).all(|cell| cell.marked));
-        any_row || any_col
+
+    /// Cell indices of every winning line on a `rows` x `cols` board.
+    fn lines(&self, rows: usize, cols: usize) -> Vec<Vec<usize>> {
+        let lines = match self {
+            Self::Rows => (0..rows)
+                .map(|r| (0..cols).map(|c| r * cols + c).collect())
+                .collect(),
+            Self::Columns => (0..cols)
+                .map(|c| (0..rows).map(|r| r * cols + c).collect())
+                .collect(),
+            Self::Diagonals if rows == cols => vec![
+                (0..rows).map(|i| i * cols + i).collect(),
+                (0..rows).map(|i| i * cols + cols - 1 - i).collect(),
+            ],
+            Self::Diagonals => vec![],
+            Self::FourCorners => vec![vec![0, cols - 1, (rows - 1) * cols, rows * cols - 1]
+                .into_iter()
+                .unique()
+                .collect()],
+            Self::Blackout => vec![(0..rows * cols).collect()],
+            Self::Pattern(mask) => vec![(0..rows)
+                .cartesian_product(0..cols)
+                .filter(|(r, c)| mask.get(*r).and_then(|row| row.get(*c)) == Some(&true))
+                .map(|(r, c)| r * cols + c)
+                .collect()],
+            Self::Any(rules) => rules
+                .iter()
+                .flat_map(|rule| rule.lines(rows, cols))
+                .collect(),
+        };
+        lines.into_iter().filter(|line| !line.is_empty()).collect()
     }
+}
+
+impl Board {
     fn unmarked_sum(&self) -> usize {
-        self.iter()
-            .flatten()
+        self.cells
+            .iter()
             .filter(|cell| !cell.marked)
             .map(|cell| cell.value)
             .sum()
     }
 }
 
+/// Winning lines for one board size, and the lines going through each cell.
+#[derive(Clone, Debug)]
+struct LineLayout {
+    lines: Vec<Vec<usize>>,
+    through: Vec<Vec<usize>>,
+}
+
+impl LineLayout {
+    fn new(rule: &WinRule, rows: usize, cols: usize) -> Self {
+        let lines = rule.lines(rows, cols);
+        let mut through = vec![Vec::new(); rows * cols];
+        lines.iter().enumerate().for_each(|(l, line)| {
+            line.iter().for_each(|cell| through[*cell].push(l));
+        });
+        Self { lines, through }
+    }
+}
+
+/// Plays a deck of boards. A reverse index from numbers to the cells holding them
+/// and a hit counter per winning line let each draw only touch the cells it marks.
+#[derive(Clone, Debug)]
+struct Engine {
+    boards: Vec<Board>,
+    /// number -> (board, cell)
+    index: HashMap<usize, Vec<(usize, usize)>>,
+    layouts: Vec<LineLayout>,
+    /// layout of each board
+    layout_of: Vec<usize>,
+    /// marked cells in each line of each board
+    hits: Vec<Vec<usize>>,
+    unmarked: Vec<usize>,
+    won: Vec<bool>,
+}
+
+impl Engine {
+    fn new(boards: &[Board], rule: &WinRule) -> Self {
+        let mut sizes = HashMap::new();
+        let mut layouts = Vec::new();
+        let layout_of = boards
+            .iter()
+            .map(|board| {
+                *sizes.entry((board.rows, board.cols)).or_insert_with(|| {
+                    layouts.push(LineLayout::new(rule, board.rows, board.cols));
+                    layouts.len() - 1
+                })
+            })
+            .collect();
+
+        let mut index = HashMap::<_, Vec<_>>::new();
+        boards.iter().enumerate().for_each(|(b, board)| {
+            board.cells.iter().enumerate().for_each(|(c, cell)| {
+                index.entry(cell.value).or_default().push((b, c));
+            })
+        });
+
+        let mut engine = Self {
+            boards: boards.to_owned(),
+            index,
+            layouts,
+            layout_of,
+            hits: Vec::new(),
+            unmarked: Vec::new(),
+            won: Vec::new(),
+        };
+        engine.reset();
+        engine
+    }
+
+    /// Unmarks every board to play again.
+    fn reset(&mut self) {
+        self.boards
+            .iter_mut()
+            .flat_map(|board| board.cells.iter_mut())
+            .for_each(|cell| cell.marked = false);
+        self.hits = self
+            .layout_of
+            .iter()
+            .map(|l| vec![0; self.layouts[*l].lines.len()])
+            .collect();
+        self.unmarked = self.boards.iter().map(Board::unmarked_sum).collect();
+        self.won = vec![false; self.boards.len()];
+    }
+
+    /// Marks `number` and returns the boards that won with it, in deck order, along with
+    /// the index of the line they completed.
+    fn draw(&mut self, number: usize) -> Vec<(usize, usize)> {
+        let mut winners = Vec::new();
+        for &(b, c) in self.index.get(&number).into_iter().flatten() {
+            let cell = &mut self.boards[b].cells[c];
+            if cell.marked {
+                continue;
+            }
+            cell.marked = true;
+            self.unmarked[b] -= number;
+            let layout = &self.layouts[self.layout_of[b]];
+            for &l in &layout.through[c] {
+                self.hits[b][l] += 1;
+                if !self.won[b] && self.hits[b][l] == layout.lines[l].len() {
+                    self.won[b] = true;
+                    winners.push((b, l));
+                }
+            }
+        }
+        winners.sort_unstable();
+        winners
+    }
+
+    /// Draws every number once and returns the boards in the order they won. Boards
+    /// winning on the same draw are ordered by their position in the deck.
+    fn play(&mut self, numbers: &[usize]) -> Vec<Win> {
+        self.reset();
+        let mut timeline = Vec::new();
+        for (draw_index, number) in numbers.iter().enumerate() {
+            for (b, l) in self.draw(*number) {
+                let cols = self.boards[b].cols;
+                let line = &self.layouts[self.layout_of[b]].lines[l];
+                timeline.push(Win {
+                    board: b,
+                    draw_index,
+                    number: *number,
+                    score: self.unmarked[b] * number,
+                    line: line.iter().map(|i| (i / cols, i % cols)).collect(),
+                });
+            }
+            if timeline.len() == self.boards.len() {
+                break;
+            }
+        }
+        timeline
+    }
+}
+
+/// A board completing a line of its win rule.
+#[derive(Clone, Debug, PartialEq)]
+struct Win {
+    board: usize,
+    draw_index: usize,
+    number: usize,
+    score: usize,
+    /// (row, col) of the cells in the completed line
+    line: Vec<(usize, usize)>,
+}
+
+/// Small seeded generator (splitmix64), good enough to shuffle draws reproducibly.
+#[derive(Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+/// Estimated chances of a board over many shuffled draws.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BoardOdds {
+    first: f64,
+    last: f64,
+    /// mean number of draws until it wins, over the trials in which it won
+    expected_draws: f64,
+}
+
+/// Replays the deck with the draw order shuffled `trials` times. Boards winning on the
+/// same draw count in deck order, and `last` only counts trials in which every board won.
+fn fairness(
+    numbers: &[usize],
+    boards: &[Board],
+    rule: &WinRule,
+    trials: usize,
+    seed: u64,
+) -> Vec<BoardOdds> {
+    let mut engine = Engine::new(boards, rule);
+    let mut rng = Rng(seed);
+    let mut numbers = numbers.to_owned();
+    let mut first = vec![0; boards.len()];
+    let mut last = vec![0; boards.len()];
+    let mut draws = vec![(0, 0); boards.len()];
+    for _ in 0..trials {
+        rng.shuffle(&mut numbers);
+        let timeline = engine.play(&numbers);
+        if let Some(win) = timeline.first() {
+            first[win.board] += 1;
+        }
+        if timeline.len() == boards.len() {
+            last[timeline.last().unwrap().board] += 1;
+        }
+        timeline.iter().for_each(|win| {
+            draws[win.board].0 += win.draw_index + 1;
+            draws[win.board].1 += 1;
+        });
+    }
+    (0..boards.len())
+        .map(|b| BoardOdds {
+            first: first[b] as f64 / trials as f64,
+            last: last[b] as f64 / trials as f64,
+            expected_draws: match draws[b] {
+                (_, 0) => f64::INFINITY,
+                (total, wins) => total as f64 / wins as f64,
+            },
+        })
+        .collect()
+}
+
 fn main() {
     let (numbers, boards) = get_draw_boards("input/04.txt");
 
-    let (first_winning_board, last_draw) = get_first_winning_board(&numbers, &boards);
-    println!("part 1: {}", first_winning_board.unmarked_sum() * last_draw);
+    // cargo run --bin 04 -- fairness [trials] [seed]
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("fairness") {
+        let trials = args.get(2).map_or(10_000, |t| t.parse().unwrap());
+        let seed = args.get(3).map_or(0, |s| s.parse().unwrap());
+        let odds = fairness(&numbers, &boards, &WinRule::standard(), trials, seed);
+        for (b, odds) in odds.iter().enumerate() {
+            println!(
+                "board {}: first {:.4} last {:.4} expected draws {:.2}",
+                b, odds.first, odds.last, odds.expected_draws
+            );
+        }
+        return;
+    }
+
+    let timeline = play(&numbers, &boards, &WinRule::standard());
 
-    let (last_winning_board, last_draw) = get_last_winning_board(&numbers, &boards);
-    println!("part 2: {}", last_winning_board.unmarked_sum() * last_draw);
+    println!("part 1: {}", timeline.first().unwrap().score);
+    println!("part 2: {}", timeline.last().unwrap().score);
 }
 
+/// Boards are separated by blank lines and may have any size.
 fn get_draw_boards(p: impl AsRef<Path>) -> (DrawNumbers, Vec<Board>) {
     let text = std::fs::read_to_string(p.as_ref()).unwrap();
     let mut lines = text.lines();
@@ -67,53 +336,36 @@ fn get_draw_boards(p: impl AsRef<Path>) -> (DrawNumbers, Vec<Board>) {
         .next()
         .unwrap()
         .split(',')
-        .map(|n| n.parse().unwrap())
+        .map(|n| n.trim().parse().unwrap())
         .collect();
 
     let boards = lines
-        .filter(|l| !l.is_empty())
-        .chunks(5)
+        .group_by(|l| l.trim().is_empty())
         .into_iter()
-        .map(|input| {
-            input
-                .into_iter()
+        .filter(|(blank, _)| !blank)
+        .map(|(_, input)| {
+            let rows = input
                 .map(|row| {
                     row.split_ascii_whitespace()
                         .map(|n| BoardCell::new(n.parse().unwrap()))
                         .collect::<Vec<_>>()
-                        .try_into()
-                        .unwrap()
                 })
-                .collect::<Vec<_>>()
-                .try_into()
-                .unwrap()
+                .collect::<Vec<_>>();
+            let cols = rows[0].len();
+            assert!(rows.iter().all(|row| row.len() == cols), "ragged board");
+            Board {
+                rows: rows.len(),
+                cols,
+                cells: rows.into_iter().flatten().collect(),
+            }
         })
         .collect();
 
     (numbers, boards)
 }
 
-fn get_first_winning_board(numbers: &[usize], boards: &[Board]) -> (Board, usize) {
-    let mut boards = boards.to_owned();
-    for number in numbers {
-        boards.iter_mut().for_each(|board| board.draw(*number));
-        if let Some(won_board) = boards.iter().find(|board| board.won()) {
-            return (won_board.to_owned(), *number);
-        }
-    }
-    unreachable!()
-}
-
-fn get_last_winning_board(numbers: &[usize], boards: &[Board]) -> (Board, usize) {
-    let mut boards = boards.to_owned();
-    for number in numbers {
-        boards.iter_mut().for_each(|board| board.draw(*number));
-        if boards.len() == 1 && boards[0].won() {
-            return (boards[0], *number);
-        }
-        boards.retain(|board| !board.won());
-    }
-    unreachable!()
+fn play(numbers: &[usize], boards: &[Board], rule: &WinRule) -> Vec<Win> {
+    Engine::new(boards, rule).play(numbers)
 }
 
 #[cfg(test)]
@@ -123,18 +375,108 @@ mod problem04 {
     #[test]
     fn part1() {
         let (numbers, boards) = get_draw_boards("input/04.test.txt");
-        let (first_winning_board, last_draw) = get_first_winning_board(&numbers, &boards);
+        let timeline = play(&numbers, &boards, &WinRule::standard());
+        let first = timeline.first().unwrap();
 
-        assert_eq!(first_winning_board.unmarked_sum(), 188);
-        assert_eq!(last_draw, 24);
+        assert_eq!(first.score, 188 * 24);
+        assert_eq!(first.number, 24);
     }
 
     #[test]
     fn part2() {
         let (numbers, boards) = get_draw_boards("input/04.test.txt");
-        let (last_winning_board, last_draw) = get_last_winning_board(&numbers, &boards);
+        let timeline = play(&numbers, &boards, &WinRule::standard());
+        let last = timeline.last().unwrap();
+
+        assert_eq!(last.score, 148 * 13);
+        assert_eq!(last.number, 13);
+    }
+
+    #[test]
+    fn timeline() {
+        let (numbers, boards) = get_draw_boards("input/04.test.txt");
+        let timeline = play(&numbers, &boards, &WinRule::standard());
+
+        let order = timeline.iter().map(|win| win.board).collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 0, 1]);
+        assert_eq!(timeline[0].draw_index, 11);
+        assert_eq!(timeline[0].line, (0..5).map(|c| (0, c)).collect::<Vec<_>>());
+        assert!(timeline
+            .windows(2)
+            .all(|w| w[0].draw_index <= w[1].draw_index));
+    }
+
+    #[test]
+    fn win_rules() {
+        let (_, boards) = get_draw_boards("input/04.test.txt");
+        let wins = |rule, numbers: &[usize]| !play(numbers, &boards[..1], &rule).is_empty();
+
+        assert!(!wins(WinRule::FourCorners, &[22, 0, 1]));
+        assert!(wins(WinRule::FourCorners, &[22, 0, 1, 19]));
+        assert!(!wins(WinRule::standard(), &[22, 0, 1, 19]));
+
+        let diagonal = [22, 2, 14, 18, 19];
+        assert!(wins(WinRule::Diagonals, &diagonal));
+        assert!(!wins(WinRule::Blackout, &diagonal));
+
+        let plus = (0..5)
+            .map(|r| (0..5).map(|c| r == 2 || c == 2).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        assert!(!wins(WinRule::Pattern(plus.clone()), &diagonal));
+        assert!(wins(
+            WinRule::Pattern(plus),
+            &[17, 23, 14, 3, 20, 21, 9, 16, 7]
+        ));
+    }
+
+    #[test]
+    fn repeated_draws() {
+        let (numbers, boards) = get_draw_boards("input/04.test.txt");
+        let mut engine = Engine::new(&boards, &WinRule::standard());
+        let repeated = numbers.iter().flat_map(|n| [*n, *n]).collect::<Vec<_>>();
+        let timeline = engine.play(&repeated);
+        assert_eq!(timeline.len(), 3);
+        assert_eq!(timeline[0].score, 188 * 24);
+        assert_eq!(
+            engine.play(&numbers),
+            play(&numbers, &boards, &WinRule::standard())
+        );
+    }
+
+    #[test]
+    fn board_sizes() {
+        let (numbers, boards) = get_draw_boards("input/04.sizes.test.txt");
+        assert_eq!((boards[0].rows, boards[0].cols), (2, 3));
+        assert_eq!((boards[1].rows, boards[1].cols), (3, 2));
+
+        let timeline = play(&numbers, &boards, &WinRule::Columns);
+        assert_eq!(timeline[0].board, 0);
+        assert_eq!(timeline[0].score, 2 + 3 + 5 + 6);
+        assert_eq!(timeline[0].line, vec![(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn monte_carlo() {
+        let (numbers, boards) = get_draw_boards("input/04.test.txt");
+        let rule = WinRule::standard();
+        let odds = fairness(&numbers, &boards, &rule, 1000, 42);
+        assert_eq!(odds, fairness(&numbers, &boards, &rule, 1000, 42));
+        assert_ne!(odds, fairness(&numbers, &boards, &rule, 1000, 7));
+
+        let total = |f: fn(&BoardOdds) -> f64| odds.iter().map(f).sum::<f64>();
+        assert!((total(|o| o.first) - 1.0).abs() < 1e-9);
+        assert!((total(|o| o.last) - 1.0).abs() < 1e-9);
+        assert!(odds
+            .iter()
+            .all(|o| (5.0..=27.0).contains(&o.expected_draws)));
 
-        assert_eq!(last_winning_board.unmarked_sum(), 148);
-        assert_eq!(last_draw, 13);
+        let mut shuffled = numbers.clone();
+        Rng(1).shuffle(&mut shuffled);
+        assert_ne!(shuffled, numbers);
+        shuffled.sort_unstable();
+        assert_eq!(
+            shuffled,
+            numbers.iter().copied().sorted().collect::<Vec<_>>()
+        );
     }
 }
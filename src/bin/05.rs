@@ -1,11 +1,15 @@
+use itertools::Itertools;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::Path,
     str::{FromStr, Split},
 };
 
 type Point = [usize; 2];
 
+/// Largest coordinate accepted, so products of coordinates can't overflow.
+const MAX_COORDINATE: usize = i32::MAX as usize;
+
 #[derive(Clone, Copy, Debug, Default)]
 struct Line {
     start: Point,
@@ -21,60 +25,223 @@ impl Line {
         !(self.start[0] == self.end[0] || self.start[1] == self.end[1])
     }
 
-    fn points(&self) -> Vec<Point> {
-        fn step(val: i32) -> i32 {
-            match val {
-                m if m > 0 => -1,
-                m if m < 0 => 1,
-                _ => 0,
-            }
+    fn delta(&self) -> [i64; 2] {
+        [
+            self.end[0] as i64 - self.start[0] as i64,
+            self.end[1] as i64 - self.start[1] as i64,
+        ]
+    }
+
+    /// Number of steps from `start` to `end`.
+    fn steps(&self) -> i64 {
+        let [dx, dy] = self.delta();
+        gcd(dx.abs(), dy.abs())
+    }
+
+    /// Smallest step between consecutive lattice points of the line.
+    fn step(&self) -> [i64; 2] {
+        let [dx, dy] = self.delta();
+        match self.steps() {
+            0 => [0, 0],
+            n => [dx / n, dy / n],
+        }
+    }
+
+    /// Direction of the line regardless of which end it starts from. A single point
+    /// is treated as a horizontal line.
+    fn direction(&self) -> [i64; 2] {
+        match self.step() {
+            [0, 0] => [1, 0],
+            [a, b] if a < 0 || (a == 0 && b < 0) => [-a, -b],
+            step => step,
         }
-        let step_x = step(self.start[0] as i32 - self.end[0] as i32);
-        let step_y = step(self.start[1] as i32 - self.end[1] as i32);
+    }
 
-        let mut points = vec![self.start];
-        let [mut x, mut y] = self.start;
+    /// Same for every point on the infinite line through this one.
+    fn key(&self) -> i64 {
+        cross(self.direction(), signed(self.start))
+    }
+
+    /// Position of the ends along the direction, `|direction|^2` apart per point.
+    fn span(&self) -> (i64, i64) {
+        let d = self.direction();
+        let (a, b) = (dot(d, signed(self.start)), dot(d, signed(self.end)));
+        (a.min(b), a.max(b))
+    }
 
-        while [x, y] != self.end {
-            x = (x as i32 + step_x) as usize;
-            y = (y as i32 + step_y) as usize;
-            points.push([x, y]);
+    fn contains(&self, p: [i64; 2]) -> bool {
+        let (lo, hi) = self.span();
+        let pos = dot(self.direction(), p);
+        cross(self.direction(), p) == self.key() && lo <= pos && pos <= hi
+    }
+
+    /// The only point two lines going in different directions may share.
+    fn crossing(&self, other: &Line) -> Option<[i64; 2]> {
+        let ([a1, b1], [a2, b2]) = (self.direction(), other.direction());
+        let (k1, k2) = (self.key(), other.key());
+        let det = a1 * b2 - a2 * b1;
+        if det == 0 {
+            return None;
         }
+        // the numerators grow with the cube of the coordinates
+        let [a1, b1, a2, b2, k1, k2, det] = [a1, b1, a2, b2, k1, k2, det].map(i128::from);
+        let (x, y) = (a1 * k2 - a2 * k1, b1 * k2 - b2 * k1);
+        if x % det != 0 || y % det != 0 {
+            return None;
+        }
+        let p = [(x / det) as i64, (y / det) as i64];
+        (self.contains(p) && other.contains(p)).then_some(p)
+    }
 
-        points
+    fn points(&self) -> Vec<Point> {
+        let [x, y] = signed(self.start);
+        let [dx, dy] = self.step();
+        (0..=self.steps())
+            .map(|i| [(x + i * dx) as usize, (y + i * dy) as usize])
+            .collect()
     }
 }
 
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+fn signed(p: Point) -> [i64; 2] {
+    [p[0] as i64, p[1] as i64]
+}
+
+fn cross(d: [i64; 2], p: [i64; 2]) -> i64 {
+    d[1] * p[0] - d[0] * p[1]
+}
+
+fn dot(d: [i64; 2], p: [i64; 2]) -> i64 {
+    d[0] * p[0] + d[1] * p[1]
+}
+
 impl FromStr for Line {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        fn coordinate(line: &mut Split<&str>) -> Point {
+        fn coordinate(line: &mut Split<&str>) -> Result<Point, ()> {
             line.next()
-                .unwrap()
+                .ok_or(())?
                 .split(',')
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<_>>()
+                .map(|n| match n.trim().parse() {
+                    Ok(n) if n <= MAX_COORDINATE => Ok(n),
+                    _ => Err(()),
+                })
+                .collect::<Result<Vec<_>, _>>()?
                 .try_into()
-                .unwrap()
+                .map_err(|_| ())
         }
         let mut line = s.split(" -> ");
-        let start = coordinate(&mut line);
-        let end = coordinate(&mut line);
-        Ok(Line::new(start, end))
+        let start = coordinate(&mut line)?;
+        let end = coordinate(&mut line)?;
+        match line.next() {
+            None => Ok(Line::new(start, end)),
+            Some(_) => Err(()),
+        }
+    }
+}
+
+/// Number of lines covering every point of the ocean floor.
+struct VentMap {
+    density: HashMap<Point, usize>,
+}
+
+impl VentMap {
+    fn new(lines: &[Line]) -> Self {
+        Self {
+            density: lines.iter().flat_map(|line| line.points()).counts(),
+        }
+    }
+
+    fn danger_at(&self, point: Point) -> usize {
+        self.density.get(&point).copied().unwrap_or_default()
+    }
+
+    /// Number of points covered by at least `threshold` lines.
+    fn count_at_least(&self, threshold: usize) -> usize {
+        self.density.values().filter(|f| **f >= threshold).count()
+    }
+
+    /// The `n` most dangerous points, ties broken by position.
+    fn hottest(&self, n: usize) -> Vec<(Point, usize)> {
+        self.density
+            .iter()
+            .map(|(p, f)| (*p, *f))
+            .sorted_by_key(|(p, f)| (std::cmp::Reverse(*f), [p[1], p[0]]))
+            .take(n)
+            .collect()
+    }
+
+    /// Width and height of the smallest map that holds every covered point.
+    fn size(&self) -> Point {
+        self.density
+            .keys()
+            .fold([0, 0], |[w, h], [x, y]| [w.max(*x + 1), h.max(*y + 1)])
+    }
+
+    /// Rows of the map as in the puzzle, `.` where there are no lines, `+` where there
+    /// are more than nine.
+    fn diagram(&self) -> String {
+        let [width, height] = self.size();
+        (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| match self.danger_at([x, y]) {
+                        0 => '.',
+                        f @ 1..=9 => char::from_digit(f as u32, 10).unwrap(),
+                        _ => '+',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    /// The map as a plain PGM image, brighter where more lines overlap.
+    fn to_pgm(&self) -> String {
+        let [width, height] = self.size();
+        let max = self.density.values().max().copied().unwrap_or(1);
+        let mut pgm = format!("P2\n{} {}\n{}\n", width, height, max);
+        for y in 0..height {
+            let row = (0..width).map(|x| self.danger_at([x, y])).join(" ");
+            pgm.push_str(&row);
+            pgm.push('\n');
+        }
+        pgm
     }
 }
 
 fn main() {
     let lines = get_lines_of_vents("input/05.txt");
 
-    let hv_lines = lines.iter().filter(|line| !line.is_diagonal());
-    let pf_hv = overlaps(hv_lines);
-    let atleast_two_overlap_hv = pf_hv.iter().filter(|(_, &f)| f >= 2).count();
-    println!("part 1: {}", atleast_two_overlap_hv);
+    // cargo run --bin 05 -- map [threshold] [diagram | image.pgm]
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("map") {
+        let threshold = args.get(2).map_or(2, |t| t.parse().unwrap());
+        let map = VentMap::new(&lines);
+        println!("at least {}: {}", threshold, map.count_at_least(threshold));
+        for (p, f) in map.hottest(10) {
+            println!("{},{}: {}", p[0], p[1], f);
+        }
+        match args.get(3).map(String::as_str) {
+            Some("diagram") => println!("{}", map.diagram()),
+            Some(path) => std::fs::write(path, map.to_pgm()).unwrap(),
+            None => (),
+        }
+        return;
+    }
 
-    let pf = overlaps(lines.iter());
-    let atleast_two_overlap = pf.iter().filter(|(_, &f)| f >= 2).count();
-    println!("part 2: {}", atleast_two_overlap);
+    let hv_lines = lines
+        .iter()
+        .filter(|line| !line.is_diagonal())
+        .copied()
+        .collect::<Vec<_>>();
+    println!("part 1: {}", count_overlaps(&hv_lines));
+    println!("part 2: {}", count_overlaps(&lines));
 }
 
 fn get_lines_of_vents(p: impl AsRef<Path>) -> Vec<Line> {
@@ -82,14 +249,77 @@ fn get_lines_of_vents(p: impl AsRef<Path>) -> Vec<Line> {
     text.lines().map(|line| line.parse().unwrap()).collect()
 }
 
-fn overlaps<'a>(lines: impl Iterator<Item = &'a Line>) -> HashMap<Point, usize> {
-    lines
-        .map(|line| line.points())
-        .flatten()
-        .fold(HashMap::new(), |mut acc, p| {
-            *acc.entry(p).or_insert(0) += 1;
-            acc
+/// Counts points covered by at least two lines without walking along the lines.
+///
+/// Lines on the same infinite line overlap on intervals, found by sweeping over their
+/// ends. Any other pair shares at most their crossing point. Overlaps of different
+/// infinite lines can only meet at crossing points, which makes up for counting
+/// those more than once.
+fn count_overlaps(lines: &[Line]) -> usize {
+    let mut collinear: HashMap<_, Vec<_>> = HashMap::new();
+    lines.iter().for_each(|line| {
+        collinear
+            .entry((line.direction(), line.key()))
+            .or_default()
+            .push(line.span());
+    });
+
+    let overlaps = collinear
+        .into_iter()
+        .map(|(group, spans)| {
+            let mut ends = spans
+                .iter()
+                .flat_map(|(lo, hi)| [(*lo, 0, 1), (*hi, 1, -1)])
+                .collect::<Vec<_>>();
+            ends.sort_unstable();
+            let mut overlapping = Vec::new();
+            let mut covering = 0;
+            for (pos, _, change) in ends {
+                covering += change;
+                match (covering, change) {
+                    (2, 1) => overlapping.push((pos, pos)),
+                    (1, -1) => overlapping.last_mut().unwrap().1 = pos,
+                    _ => (),
+                }
+            }
+            (group, overlapping)
+        })
+        .filter(|(_, overlapping)| !overlapping.is_empty())
+        .collect::<HashMap<_, _>>();
+
+    let mut count = overlaps
+        .iter()
+        .flat_map(|((d, _), overlapping)| {
+            let norm = dot(*d, *d);
+            overlapping.iter().map(move |(lo, hi)| (hi - lo) / norm + 1)
         })
+        .sum::<i64>();
+
+    let directions = overlaps
+        .keys()
+        .map(|(d, _)| *d)
+        .unique()
+        .collect::<Vec<_>>();
+    let crossings = lines
+        .iter()
+        .tuple_combinations()
+        .filter_map(|(a, b)| a.crossing(b))
+        .collect::<HashSet<_>>();
+    for p in crossings {
+        let within = directions
+            .iter()
+            .filter_map(|d| overlaps.get(&(*d, cross(*d, p))).map(|o| (d, o)))
+            .filter(|(d, overlapping)| {
+                let pos = dot(**d, p);
+                overlapping.iter().any(|(lo, hi)| *lo <= pos && pos <= *hi)
+            })
+            .count() as i64;
+        count += match within {
+            0 => 1,
+            m => 1 - m,
+        };
+    }
+    count as usize
 }
 
 #[cfg(test)]
@@ -99,17 +329,88 @@ mod problem05 {
     #[test]
     fn part1() {
         let lines = get_lines_of_vents("input/05.test.txt");
-        let hv_lines = lines.iter().filter(|line| !line.is_diagonal());
-        let pf = overlaps(hv_lines);
-        let atleast_two_overlap = pf.iter().filter(|(_, &f)| f >= 2).count();
-        assert_eq!(atleast_two_overlap, 5);
+        let hv_lines = lines
+            .iter()
+            .filter(|line| !line.is_diagonal())
+            .copied()
+            .collect::<Vec<_>>();
+        assert_eq!(count_overlaps(&hv_lines), 5);
     }
 
     #[test]
     fn part2() {
         let lines = get_lines_of_vents("input/05.test.txt");
-        let pf = overlaps(lines.iter());
-        let atleast_two_overlap = pf.iter().filter(|(_, &f)| f >= 2).count();
-        assert_eq!(atleast_two_overlap, 12);
+        assert_eq!(count_overlaps(&lines), 12);
+    }
+
+    #[test]
+    fn overlaps_match_points() {
+        let lines = get_lines_of_vents("input/05.txt");
+        let points = VentMap::new(&lines).count_at_least(2);
+        assert_eq!(count_overlaps(&lines), points);
+    }
+
+    #[test]
+    fn vent_map() {
+        let lines = get_lines_of_vents("input/05.test.txt");
+        let map = VentMap::new(&lines);
+        let diagram = "\
+            1.1....11.\n\
+            .111...2..\n\
+            ..2.1.111.\n\
+            ...1.2.2..\n\
+            .112313211\n\
+            ...1.2....\n\
+            ..1...1...\n\
+            .1.....1..\n\
+            1.......1.\n\
+            222111....";
+        assert_eq!(map.diagram(), diagram);
+        assert_eq!(map.danger_at([4, 4]), 3);
+        assert_eq!(map.danger_at([9, 9]), 0);
+        assert_eq!(map.count_at_least(2), 12);
+        assert_eq!(map.count_at_least(3), 2);
+        assert_eq!(map.hottest(3), vec![([4, 4], 3), ([6, 4], 3), ([7, 1], 2)]);
+        assert!(map.to_pgm().starts_with("P2\n10 10\n3\n1 0 1 0"));
+    }
+
+    #[test]
+    fn any_angle() {
+        let line: Line = "1,1 -> 7,4".parse().unwrap();
+        assert_eq!(line.points(), vec![[1, 1], [3, 2], [5, 3], [7, 4]]);
+        let line: Line = "4,4 -> 4,4".parse().unwrap();
+        assert_eq!(line.points(), vec![[4, 4]]);
+
+        let lines = [
+            line,
+            "1,1 -> 7,4".parse().unwrap(),
+            "0,4 -> 6,1".parse().unwrap(),
+            "0,0 -> 9,3".parse().unwrap(),
+            "7,4 -> 8,0".parse().unwrap(),
+            "0,5 -> 5,0".parse().unwrap(),
+        ];
+        let points = VentMap::new(&lines).count_at_least(2);
+        assert_eq!(points, 3);
+        assert_eq!(count_overlaps(&lines), points);
+
+        assert!("1,1 -> 7".parse::<Line>().is_err());
+        assert!("1,1 -> 7,4 -> 8,8".parse::<Line>().is_err());
+        assert!("1,1 -> 7,-4".parse::<Line>().is_err());
+        assert!("1,1 -> 7,4000000000".parse::<Line>().is_err());
+    }
+
+    #[test]
+    fn huge_coordinates() {
+        let lines = [
+            Line::new([0, 0], [4_000_000, 4_000_000]),
+            Line::new([1_000_000, 1_000_000], [9_000_000, 9_000_000]),
+            Line::new([0, 2_000_000], [5_000_000, 2_000_000]),
+            Line::new([3_000_000, 0], [3_000_000, 9_000_000]),
+            // crosses the first line between two points
+            Line::new([0, 1], [1, 0]),
+        ];
+        // overlap of the diagonals, plus the crossing at (3M, 2M); the crossings at
+        // (2M, 2M) and (3M, 3M) lie on the overlap already
+        assert_eq!(count_overlaps(&lines), 3_000_001 + 1);
     }
 }
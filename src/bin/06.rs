@@ -1,48 +1,121 @@
 use std::{path::Path, str::FromStr};
 
+/// Parameters of a population's life cycle. The default is the lanternfish
+/// from the puzzle: a 7-day cycle and a 2-day delay for newborns.
 #[derive(Clone, Copy, Debug, PartialEq)]
+struct Lifecycle {
+    /// timer a parent is set to after spawning
+    reset: usize,
+    /// timer a newborn starts with
+    newborn: usize,
+    /// fish older than this many days die; fish from the initial state start at age 0
+    max_age: Option<usize>,
+    /// fraction of every (age, timer) group that dies at the start of each day, rounded down
+    mortality: Option<f64>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self {
+            reset: 6,
+            newborn: 8,
+            max_age: None,
+            mortality: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct Fish {
-    /// index corresponds to timer and value corresponds to numbers of fish with that timer
-    timers: [usize; 9],
+    lifecycle: Lifecycle,
+    /// outer index corresponds to age (a single row if age isn't tracked), inner index
+    /// corresponds to timer and value corresponds to numbers of fish with that age and timer
+    timers: Vec<Vec<usize>>,
 }
 
 impl FromStr for Fish {
     type Err = ();
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut timers = [0; 9];
-        s.split(',').map(|n| n.parse().unwrap()).for_each(|t: u8| {
-            timers[t as usize] += 1;
-        });
-        Ok(Self { timers })
+        Fish::with_lifecycle(s, Lifecycle::default())
     }
 }
 
 impl Fish {
-    const RESET: usize = 6;
+    fn with_lifecycle(s: &str, lifecycle: Lifecycle) -> Result<Self, ()> {
+        if let Some(rate) = lifecycle.mortality {
+            if !(0.0..=1.0).contains(&rate) {
+                return Err(());
+            }
+        }
+        let initial = s
+            .trim()
+            .split(',')
+            .map(|n| n.parse().map_err(|_| ()))
+            .collect::<Result<Vec<usize>, _>>()?;
+        let width = initial
+            .iter()
+            .copied()
+            .chain([lifecycle.reset, lifecycle.newborn])
+            .max()
+            .unwrap()
+            + 1;
+        let ages = lifecycle.max_age.map_or(1, |max_age| max_age + 1);
+        let mut timers = vec![vec![0; width]; ages];
+        initial.into_iter().for_each(|t| timers[0][t] += 1);
+        Ok(Self { lifecycle, timers })
+    }
 
     fn next(&mut self) {
-        let spawners = self.timers[0];
-        self.timers.rotate_left(1);
-        self.timers[Self::RESET] += spawners;
+        let Lifecycle {
+            reset,
+            newborn,
+            max_age,
+            mortality,
+        } = self.lifecycle;
+
+        if let Some(rate) = mortality {
+            self.timers.iter_mut().flatten().for_each(|count| {
+                *count -= (*count as f64 * rate) as usize;
+            });
+        }
+
+        let mut spawners = 0;
+        for row in self.timers.iter_mut() {
+            let parents = std::mem::take(&mut row[0]);
+            row.rotate_left(1);
+            row[reset] += parents;
+            spawners += parents;
+        }
+
+        if max_age.is_some() {
+            // everyone gets a day older, the oldest row dies and newborns take its place
+            self.timers.rotate_right(1);
+            self.timers[0].iter_mut().for_each(|count| *count = 0);
+        }
+        self.timers[0][newborn] += spawners;
     }
 
-    fn next_n(&mut self, n: usize) {
+    /// Population count at the start and after each of the next `n` days.
+    fn history(&mut self, n: usize) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(n + 1);
+        counts.push(self.count());
         for _ in 0..n {
             self.next();
+            counts.push(self.count());
         }
+        counts
     }
 
     fn count(&self) -> usize {
-        self.timers.iter().sum()
+        self.timers.iter().flatten().sum()
     }
 }
 
 fn main() {
     let mut fish = get_fish("input/06.txt");
-    fish.next_n(80);
-    println!("part 1: {}", fish.count());
-    fish.next_n(256 - 80);
-    println!("part 2: {}", fish.count());
+    let counts = fish.history(256);
+    println!("part 1: {}", counts[80]);
+    println!("part 2: {}", counts[256]);
 }
 
 fn get_fish(p: impl AsRef<Path>) -> Fish {
@@ -66,15 +139,61 @@ mod problem06 {
     #[test]
     fn part1() {
         let mut fish = get_fish("input/06.test.txt");
-        fish.next_n(80);
-        assert_eq!(fish.count(), 5934);
+        let counts = fish.history(80);
+        assert_eq!(counts[80], 5934);
     }
 
     #[test]
     fn part2() {
         let mut fish = get_fish("input/06.test.txt");
-        fish.next_n(80);
-        fish.next_n(256 - 80);
-        assert_eq!(fish.count(), 26984457539);
+        let counts = fish.history(256);
+        assert_eq!(counts[256], 26984457539);
+    }
+
+    #[test]
+    fn history() {
+        let mut fish = get_fish("input/06.test.txt");
+        let counts = fish.history(18);
+        assert_eq!(counts.len(), 19);
+        assert_eq!(counts[0], 5);
+        assert_eq!(counts[18], 26);
+    }
+
+    #[test]
+    fn lifecycle() {
+        let text = std::fs::read_to_string("input/06.test.txt").unwrap();
+
+        // parents spawn every day and newborns wait a day before joining in: fibonacci
+        let lifecycle = Lifecycle {
+            reset: 0,
+            newborn: 1,
+            ..Lifecycle::default()
+        };
+        let mut fish = Fish::with_lifecycle("1", lifecycle).unwrap();
+        assert_eq!(fish.history(6), vec![1, 1, 2, 3, 5, 8, 13]);
+
+        let lifecycle = Lifecycle {
+            max_age: Some(3),
+            ..Lifecycle::default()
+        };
+        let mut fish = Fish::with_lifecycle(&text, lifecycle).unwrap();
+        assert_eq!(fish.history(5), vec![5, 5, 6, 7, 4, 4]);
+
+        let lifecycle = Lifecycle {
+            mortality: Some(0.5),
+            ..Lifecycle::default()
+        };
+        let mut fish = Fish::with_lifecycle("3,3,3,4,4,4", lifecycle).unwrap();
+        fish.next();
+        assert_eq!(fish.timers, vec![vec![0, 0, 2, 2, 0, 0, 0, 0, 0]]);
+        assert_eq!(fish.history(3), vec![4, 2, 2, 3]);
+
+        for rate in [1.5, -0.1, f64::NAN] {
+            let lifecycle = Lifecycle {
+                mortality: Some(rate),
+                ..Lifecycle::default()
+            };
+            assert!(Fish::with_lifecycle("3,3", lifecycle).is_err());
+        }
     }
 }
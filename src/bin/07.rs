@@ -2,9 +2,9 @@ use std::path::Path;
 
 fn main() {
     let positions = get_positions("input/07.txt");
-    let (_best_pos, fuel) = best_alignment(&positions);
+    let (_best_pos, fuel) = Linear.best_alignment(&positions);
     println!("part 1: {}", fuel);
-    let (_best_pos, fuel) = best_alignment_v2(&positions);
+    let (_best_pos, fuel) = Triangular.best_alignment(&positions);
     println!("part 2: {}", fuel);
 }
 
@@ -12,46 +12,148 @@ fn get_positions(p: impl AsRef<Path>) -> Vec<usize> {
     std::fs::read_to_string(p)
         .unwrap()
         .split(',')
-        .map(|n| n.parse().unwrap())
+        .map(|n| n.trim().parse().unwrap())
         .collect()
 }
 
-fn best_alignment(positions: &[usize]) -> (usize, usize) {
-    let least = *positions.iter().min().unwrap();
-    let highest = *positions.iter().max().unwrap();
-    (least..=highest)
-        .into_iter()
-        .map(|pos| {
-            let sum = positions
-                .iter()
-                .map(|p| i32::abs(*p as i32 - pos as i32) as usize)
-                .sum();
-            (pos, sum)
-        })
-        .min_by_key(|pair| pair.1)
-        .unwrap()
+/// Fuel a crab burns to move a number of steps. The best alignment is found by trying
+/// every position unless the cost is known to be convex, which keeps the total fuel
+/// convex in the target position as well.
+trait FuelCost {
+    fn fuel(&self, steps: usize) -> usize;
+
+    fn total_fuel(&self, positions: &[usize], target: usize) -> usize {
+        positions
+            .iter()
+            .map(|p| self.fuel(p.abs_diff(target)))
+            .sum()
+    }
+
+    /// Position that needs the least fuel, together with that fuel.
+    fn best_alignment(&self, positions: &[usize]) -> (usize, usize) {
+        let least = *positions.iter().min().unwrap();
+        let highest = *positions.iter().max().unwrap();
+        (least..=highest)
+            .map(|pos| (pos, self.total_fuel(positions, pos)))
+            .min_by_key(|pair| pair.1)
+            .unwrap()
+    }
+}
+
+/// Opts a cost into the ternary search, which is only correct for convex costs.
+#[allow(dead_code)]
+struct Convex<F>(F);
+
+/// One unit of fuel per step.
+struct Linear;
+
+/// Each step costs one more unit of fuel than the previous one.
+struct Triangular;
+
+/// Fuel is the square of the steps.
+#[allow(dead_code)]
+struct Quadratic;
+
+impl FuelCost for Linear {
+    fn fuel(&self, steps: usize) -> usize {
+        steps
+    }
+
+    /// The sum of distances is minimised by the median.
+    fn best_alignment(&self, positions: &[usize]) -> (usize, usize) {
+        let mut sorted = positions.to_owned();
+        let mid = (sorted.len() - 1) / 2;
+        let (_, &mut median, _) = sorted.select_nth_unstable(mid);
+        (median, self.total_fuel(positions, median))
+    }
+}
+
+impl FuelCost for Triangular {
+    fn fuel(&self, steps: usize) -> usize {
+        steps * (steps + 1) / 2
+    }
+
+    /// The sum of triangular costs is minimised within half a step of the mean.
+    fn best_alignment(&self, positions: &[usize]) -> (usize, usize) {
+        around_mean(positions, |pos| self.total_fuel(positions, pos))
+    }
+}
+
+impl FuelCost for Quadratic {
+    fn fuel(&self, steps: usize) -> usize {
+        steps * steps
+    }
+
+    /// The sum of squares is minimised by the mean.
+    fn best_alignment(&self, positions: &[usize]) -> (usize, usize) {
+        around_mean(positions, |pos| self.total_fuel(positions, pos))
+    }
+}
+
+impl<F: Fn(usize) -> usize> FuelCost for F {
+    fn fuel(&self, steps: usize) -> usize {
+        self(steps)
+    }
+}
+
+impl<F: FuelCost> FuelCost for Convex<F> {
+    fn fuel(&self, steps: usize) -> usize {
+        self.0.fuel(steps)
+    }
+
+    fn best_alignment(&self, positions: &[usize]) -> (usize, usize) {
+        let least = *positions.iter().min().unwrap();
+        let highest = *positions.iter().max().unwrap();
+        minimise_convex(least, highest, |pos| self.total_fuel(positions, pos))
+    }
 }
 
-fn best_alignment_v2(positions: &[usize]) -> (usize, usize) {
-    let fuel_for_steps = |n: usize| n * (n + 1) / 2;
-    let least = *positions.iter().min().unwrap();
-    let highest = *positions.iter().max().unwrap();
-    (least..=highest)
-        .into_iter()
-        .map(|pos| {
-            let sum = positions
-                .iter()
-                .map(|p| {
-                    let n = i32::abs(*p as i32 - pos as i32) as usize;
-                    fuel_for_steps(n)
-                })
-                .sum();
-            (pos, sum)
-        })
+/// Minimises `cost` over `lo..=hi` with a ternary search. `cost` has to be convex,
+/// i.e. the difference between neighbouring costs never decreases. Being merely
+/// unimodal isn't enough, a plateau on one side of the minimum can hide it.
+fn minimise_convex(mut lo: usize, mut hi: usize, cost: impl Fn(usize) -> usize) -> (usize, usize) {
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        match cost(m1).cmp(&cost(m2)) {
+            std::cmp::Ordering::Less => hi = m2 - 1,
+            std::cmp::Ordering::Greater => lo = m1 + 1,
+            std::cmp::Ordering::Equal => (lo, hi) = (m1, m2),
+        }
+    }
+    (lo..=hi)
+        .map(|pos| (pos, cost(pos)))
         .min_by_key(|pair| pair.1)
         .unwrap()
 }
 
+/// Checks only the integers right around the mean of `positions`.
+fn around_mean(positions: &[usize], cost: impl Fn(usize) -> usize) -> (usize, usize) {
+    let n = positions.len();
+    let total: usize = positions.iter().sum();
+    let floor = total / n;
+    let ceil = total.div_ceil(n);
+    minimise_convex(floor.saturating_sub(1), ceil + 1, cost)
+}
+
+/// Aligns crabs in `D` dimensions where each crab pays for every axis separately,
+/// so every axis can be aligned on its own.
+#[allow(dead_code)]
+fn best_alignment_nd<const D: usize>(
+    cost: &impl FuelCost,
+    points: &[[usize; D]],
+) -> ([usize; D], usize) {
+    let mut best = [0; D];
+    let mut fuel = 0;
+    for (axis, best) in best.iter_mut().enumerate() {
+        let positions = points.iter().map(|p| p[axis]).collect::<Vec<_>>();
+        let (pos, axis_fuel) = cost.best_alignment(&positions);
+        *best = pos;
+        fuel += axis_fuel;
+    }
+    (best, fuel)
+}
+
 #[cfg(test)]
 mod problem07 {
     use super::*;
@@ -59,7 +161,7 @@ mod problem07 {
     #[test]
     fn part1() {
         let positions = get_positions("input/07.test.txt");
-        let (best_pos, fuel) = best_alignment(&positions);
+        let (best_pos, fuel) = Linear.best_alignment(&positions);
         assert_eq!(2, best_pos);
         assert_eq!(37, fuel);
     }
@@ -67,8 +169,55 @@ mod problem07 {
     #[test]
     fn part2() {
         let positions = get_positions("input/07.test.txt");
-        let (best_pos, fuel) = best_alignment_v2(&positions);
+        let (best_pos, fuel) = Triangular.best_alignment(&positions);
         assert_eq!(5, best_pos);
         assert_eq!(168, fuel);
     }
+
+    #[test]
+    fn convex_minimum() {
+        let positions = get_positions("input/07.test.txt");
+        let cost = |pos| positions.iter().map(|p| p.abs_diff(pos)).sum();
+        assert_eq!(minimise_convex(0, 16, cost).1, 37);
+
+        let positions: [usize; 4] = [0, 1_000_000_000, 3, 999_999_999];
+        let cost = |pos| positions.iter().map(|p| p.abs_diff(pos).pow(2)).sum();
+        assert_eq!(minimise_convex(0, 1_000_000_000, cost).0, 500_000_000);
+    }
+
+    #[test]
+    fn fuel_costs() {
+        let positions = get_positions("input/07.test.txt");
+        let (best_pos, _) = Quadratic.best_alignment(&positions);
+        assert_eq!(5, best_pos);
+
+        let cubic = |n: usize| n.pow(3);
+        let (best_pos, fuel) = cubic.best_alignment(&positions);
+        assert_eq!(fuel, cubic.total_fuel(&positions, best_pos));
+        assert!((0..=16).all(|pos| cubic.total_fuel(&positions, pos) >= fuel));
+
+        assert_eq!((best_pos, fuel), Convex(cubic).best_alignment(&positions));
+
+        let (best_pos, fuel) = Triangular.best_alignment(&positions);
+        assert_eq!(
+            (best_pos, fuel),
+            (|n| Triangular.fuel(n)).best_alignment(&positions)
+        );
+    }
+
+    #[test]
+    fn non_convex_cost() {
+        // grows with the steps but isn't convex, a ternary search ends up at 22
+        let sqrt = |n: usize| n.isqrt();
+        assert_eq!(sqrt.best_alignment(&[18, 27]), (18, 3));
+    }
+
+    #[test]
+    fn multi_dimensional() {
+        let points = [[1, 5, 0], [3, 1, 0], [10, 2, 7]];
+        assert_eq!(best_alignment_nd(&Linear, &points), ([3, 2, 0], 9 + 4 + 7));
+        let (best, fuel) = best_alignment_nd(&Triangular, &points);
+        assert_eq!(best, [4, 2, 2]);
+        assert_eq!(fuel, (6 + 1 + 21) + (6 + 1) + (3 + 3 + 15));
+    }
 }
@@ -1,110 +1,466 @@
-///  aaaa    Assign unique value to each segment.
-/// b    c   a = 2 b = 6 c = 3 d = 6
-/// b    c   e = 7 f = 4 g = 5
-///  dddd
-/// e    f   E segments lits up 4 times in all 10 digits
-/// e    f   similary B 6 times F 9 times.
-///  gggg    Find E, B, F right mappings by counting occurences
+///  aaaa    The wiring is an unknown permutation of the segments.
+/// b    c   Every observed pattern has to light up the segments of some glyph,
+/// b    c   so each wire can only map to segments that are lit (or unlit)
+///  dddd    in every glyph the pattern could still be.
+/// e    f
+/// e    f   Narrow down the segments each wire can map to until nothing changes,
+///  gggg    dropping a segment from the other wires once a wire is known.
 ///
-/// For showing 1, it uses 2 segments (CF), similarly for 7 -> 3 segs, for 4 -> 4, 8 -> 7
-/// start with smallest and solve for the unknown for eg: for 1, i.e., find signal with len of 2
-/// correlate to CF (original mapping), we already know mapping for F
-/// we find C by solving eq c * 4 (value assigned to f) = 3 * 4 (multiply values of segments for 1)
-/// repeat the process for 7, 4, 8 and all mappings will be known
+/// If some wire is still undecided, try each of its segments in turn and repeat.
+/// A single consistent wiring decodes the display, none means the patterns contradict
+/// each other and more than one means there weren't enough patterns to tell them apart.
 #[macro_use]
 extern crate lazy_static;
-use std::{
-    collections::{BTreeSet, HashMap},
-    path::Path,
-};
+use itertools::Itertools;
+use std::{collections::HashMap, path::Path};
 
 lazy_static! {
-    static ref SIGNAL_MAP: HashMap<BTreeSet<usize>, usize> = {
-        let mut m = HashMap::new();
-        m.insert(BTreeSet::from([3, 4]), 1);
-        m.insert(BTreeSet::from([2, 3, 6, 7, 5]), 2);
-        m.insert(BTreeSet::from([2, 3, 6, 4, 5]), 3);
-        m.insert(BTreeSet::from([8, 6, 3, 4]), 4);
-        m.insert(BTreeSet::from([2, 8, 6, 4, 5]), 5);
-        m.insert(BTreeSet::from([2, 8, 7, 5, 4, 6]), 6);
-        m.insert(BTreeSet::from([2, 3, 4]), 7);
-        m.insert(BTreeSet::from([2, 3, 4, 5, 6, 7, 8]), 8);
-        m.insert(BTreeSet::from([6, 8, 2, 3, 4, 5]), 9);
-        m.insert(BTreeSet::from([2, 3, 4, 5, 7, 8]), 0);
-        m
-    };
-}
-
-const E_REPS: usize = 4;
-const B_REPS: usize = 6;
-const F_REPS: usize = 9;
-const E_UNIQV: usize = 7;
-const B_UNIQV: usize = 8;
-const F_UNIQV: usize = 4;
+    static ref SEVEN_SEGMENT: Layout = Layout::new(
+        7,
+        &[
+            ('0', "abcefg"),
+            ('1', "cf"),
+            ('2', "acdeg"),
+            ('3', "acdfg"),
+            ('4', "bcdf"),
+            ('5', "abdfg"),
+            ('6', "abdefg"),
+            ('7', "acf"),
+            ('8', "abcdefg"),
+            ('9', "abcdfg"),
+        ],
+    );
 
+    ///  aaaaa
+    /// fi j kb
+    /// f ijk b
+    ///  gg hh
+    /// e lmn c
+    /// el m nc
+    ///  ddddd
+    static ref FOURTEEN_SEGMENT: Layout = Layout::new(
+        14,
+        &[
+            ('0', "abcdefkl"),
+            ('1', "bc"),
+            ('2', "abdegh"),
+            ('3', "abcdh"),
+            ('4', "bcfgh"),
+            ('5', "adfgn"),
+            ('6', "acdefgh"),
+            ('7', "abc"),
+            ('8', "abcdefgh"),
+            ('9', "abcdfgh"),
+            ('A', "abcefgh"),
+            ('B', "abcdhjm"),
+            ('C', "adef"),
+            ('D', "abcdjm"),
+            ('E', "adefgh"),
+            ('F', "aefg"),
+            ('G', "acdefh"),
+            ('H', "bcefgh"),
+            ('I', "adjm"),
+            ('J', "bcde"),
+            ('K', "efgkn"),
+            ('L', "def"),
+            ('M', "bcefik"),
+            ('N', "bcefin"),
+            ('O', "abcdef"),
+            ('P', "abefgh"),
+            ('Q', "abcdefn"),
+            ('R', "abefghn"),
+            ('S', "acdfgh"),
+            ('T', "ajm"),
+            ('U', "bcdef"),
+            ('V', "efkl"),
+            ('W', "bcefln"),
+            ('X', "ikln"),
+            ('Y', "ikm"),
+            ('Z', "adkl"),
+            ('+', "ghjm"),
+            ('-', "gh"),
+            ('/', "kl"),
+        ],
+    );
+
+    ///  aa bb
+    /// hi j kc
+    /// h ijk c
+    ///  ll mm
+    /// g nop d
+    /// gn o pd
+    ///  ff ee
+    static ref SIXTEEN_SEGMENT: Layout = Layout::new(
+        16,
+        &[
+            ('0', "abcdefghkn"),
+            ('1', "cd"),
+            ('2', "abcefglm"),
+            ('3', "abcdefm"),
+            ('4', "cdhlm"),
+            ('5', "abefhlp"),
+            ('6', "abdefghlm"),
+            ('7', "abcd"),
+            ('8', "abcdefghlm"),
+            ('9', "abcdefhlm"),
+            ('A', "abcdghlm"),
+            ('B', "abcdefjmo"),
+            ('C', "abefgh"),
+            ('D', "abcdefjo"),
+            ('E', "abefghlm"),
+            ('F', "abghl"),
+            ('G', "abdefghm"),
+            ('H', "cdghlm"),
+            ('I', "abefjo"),
+            ('J', "cdefg"),
+            ('K', "ghklp"),
+            ('L', "efgh"),
+            ('M', "cdghik"),
+            ('N', "cdghip"),
+            ('O', "abcdefgh"),
+            ('P', "abcghlm"),
+            ('Q', "abcdefghp"),
+            ('R', "abcghlmp"),
+            ('S', "abdefhlm"),
+            ('T', "abjo"),
+            ('U', "cdefgh"),
+            ('V', "ghkn"),
+            ('W', "cdghnp"),
+            ('X', "iknp"),
+            ('Y', "iko"),
+            ('Z', "abefkn"),
+            ('+', "jlmo"),
+            ('-', "lm"),
+            ('/', "kn"),
+            ('[', "afgh"),
+            (']', "bcde"),
+        ],
+    );
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum DecodeError {
+    UnknownWire(char),
+    UnknownGlyph(String),
+    Inconsistent,
+    Ambiguous,
+    /// only seven-segment displays can be drawn
+    NotSevenSegment,
+}
+
+/// Segments of a display named from `a` onwards, and the glyphs it can show.
 #[derive(Debug, Clone)]
-struct SegmentDisplay {
-    uniq: HashMap<char, usize>,
-}
-
-impl SegmentDisplay {
-    fn deduce_from(patterns: &str) -> Self {
-        let occurences = patterns.split(' ').fold(HashMap::new(), |mut map, p| {
-            p.chars().for_each(|c| *map.entry(c).or_insert(0) += 1);
-            map
-        });
-
-        let mut uniq = HashMap::new();
-        for (key, value) in occurences {
-            match value {
-                E_REPS => uniq.insert(key, E_UNIQV),
-                B_REPS => uniq.insert(key, B_UNIQV),
-                F_REPS => uniq.insert(key, F_UNIQV),
-                _ => None,
-            };
+struct Layout {
+    segments: usize,
+    glyphs: Vec<(char, u32)>,
+}
+
+impl Layout {
+    fn new(segments: usize, glyphs: &[(char, &str)]) -> Self {
+        assert!(segments <= 26);
+        let mut layout = Self {
+            segments,
+            glyphs: Vec::new(),
+        };
+        layout.glyphs = glyphs
+            .iter()
+            .map(|(glyph, lit)| (*glyph, layout.to_mask(lit).unwrap()))
+            .collect();
+        layout
+    }
+
+    /// Wires and segments share names, so this works for both.
+    fn to_mask(&self, pattern: &str) -> Result<u32, DecodeError> {
+        pattern.chars().try_fold(0, |mask, c| match c {
+            'a'..='z' if ((c as u8 - b'a') as usize) < self.segments => {
+                Ok(mask | 1 << (c as u8 - b'a'))
+            }
+            _ => Err(DecodeError::UnknownWire(c)),
+        })
+    }
+
+    fn all(&self) -> u32 {
+        (1 << self.segments) - 1
+    }
+
+    fn bits(&self, mask: u32) -> impl Iterator<Item = usize> {
+        (0..self.segments).filter(move |i| mask & 1 << i != 0)
+    }
+
+    fn glyph(&self, mask: u32) -> Option<char> {
+        self.glyphs
+            .iter()
+            .find(|(_, lit)| *lit == mask)
+            .map(|(glyph, _)| *glyph)
+    }
+}
+
+/// Segments each wire may still be connected to, and glyphs each pattern may still be.
+#[derive(Debug, Clone)]
+struct Constraints<'a> {
+    layout: &'a Layout,
+    patterns: Vec<u32>,
+    domains: Vec<u32>,
+    candidates: Vec<Vec<u32>>,
+    /// (wire, segment) pairs pinned down by each round of propagation or guessing
+    trace: Vec<Vec<(usize, usize)>>,
+}
+
+impl<'a> Constraints<'a> {
+    fn new(layout: &'a Layout, patterns: Vec<u32>) -> Self {
+        let candidates = patterns
+            .iter()
+            .map(|p| {
+                layout
+                    .glyphs
+                    .iter()
+                    .map(|(_, lit)| *lit)
+                    .filter(|lit| lit.count_ones() == p.count_ones())
+                    .collect()
+            })
+            .collect();
+        Self {
+            layout,
+            patterns,
+            domains: vec![layout.all(); layout.segments],
+            candidates,
+            trace: Vec::new(),
         }
+    }
 
-        let mut disp = Self { uniq };
+    /// Narrows down domains and candidates, returns false on a contradiction.
+    fn propagate(&mut self) -> bool {
+        let all = self.layout.all();
+        let segments = self.layout.segments;
+        loop {
+            let before = self.domains.clone();
+            for i in 0..self.patterns.len() {
+                let pattern = self.patterns[i];
+                let step = self.domains.clone();
+                let domains = &self.domains;
+                self.candidates[i].retain(|glyph| {
+                    (0..segments).all(|w| match pattern & 1 << w {
+                        0 => domains[w] & !glyph & all != 0,
+                        _ => domains[w] & glyph != 0,
+                    })
+                });
+                let lit = self.candidates[i].iter().fold(0, |acc, g| acc | g);
+                let unlit = self.candidates[i].iter().fold(0, |acc, g| acc | (!g & all));
+                for (w, domain) in self.domains.iter_mut().enumerate() {
+                    *domain &= if pattern & 1 << w != 0 { lit } else { unlit };
+                }
+                self.record(&step);
+            }
+            let step = self.domains.clone();
+            for (w, known) in step.iter().enumerate() {
+                if known.count_ones() == 1 {
+                    (0..segments)
+                        .filter(|v| *v != w)
+                        .for_each(|v| self.domains[v] &= !known);
+                }
+            }
+            self.record(&step);
+            if self.domains.contains(&0) {
+                return false;
+            }
+            if self.domains == before {
+                return true;
+            }
+        }
+    }
 
-        let one = patterns.split(' ').find(|p| p.len() == 2).unwrap();
-        Self::solve_single_unknown(&mut disp, one, 3 * 4);
-        let seven = patterns.split(' ').find(|p| p.len() == 3).unwrap();
-        Self::solve_single_unknown(&mut disp, seven, 2 * 3 * 4);
-        let four = patterns.split(' ').find(|p| p.len() == 4).unwrap();
-        Self::solve_single_unknown(&mut disp, four, 8 * 6 * 3 * 4);
-        let eight = patterns.split(' ').find(|p| p.len() == 7).unwrap();
-        Self::solve_single_unknown(&mut disp, eight, 8 * 2 * 3 * 7 * 6 * 4 * 5);
+    /// Adds the wires that got pinned down since `before` to the trace.
+    fn record(&mut self, before: &[u32]) {
+        let decided = (0..self.layout.segments)
+            .filter(|w| before[*w].count_ones() > 1 && self.domains[*w].count_ones() == 1)
+            .map(|w| (w, self.domains[w].trailing_zeros() as usize))
+            .collect::<Vec<_>>();
+        if !decided.is_empty() {
+            self.trace.push(decided);
+        }
+    }
 
-        disp
+    /// Collects consistent wirings, stops looking once there are two.
+    fn search(mut self, solutions: &mut Vec<Self>) {
+        if solutions.len() > 1 || !self.propagate() {
+            return;
+        }
+        let undecided = (0..self.layout.segments)
+            .filter(|w| self.domains[*w].count_ones() > 1)
+            .min_by_key(|w| self.domains[*w].count_ones());
+        match undecided {
+            None => solutions.push(self),
+            Some(w) => {
+                for segment in self.layout.bits(self.domains[w]) {
+                    let mut guess = self.clone();
+                    guess.domains[w] = 1 << segment;
+                    guess.trace.push(vec![(w, segment)]);
+                    guess.search(solutions);
+                }
+            }
+        }
     }
+}
 
-    fn solve_single_unknown(&mut self, pattern: &str, pattern_val: usize) {
-        let mut unknown_val = pattern_val;
-        pattern.chars().for_each(|c| {
-            if let Some(val) = self.uniq.get(&c) {
-                unknown_val /= val;
+#[derive(Debug, Clone)]
+struct SegmentDisplay<'a> {
+    layout: &'a Layout,
+    /// scrambled wire to the segment it lights up
+    wiring: HashMap<char, usize>,
+    /// wires pinned down at each step of the deduction
+    trace: Vec<Vec<(char, usize)>>,
+}
+
+fn wire_name(w: usize) -> char {
+    (b'a' + w as u8) as char
+}
+
+/// Draws lit segments of a seven-segment digit with the character `label` gives them.
+fn seven_segment_art(lit: u32, label: impl Fn(usize) -> char) -> Vec<String> {
+    let segment = |s: usize| if lit & 1 << s != 0 { label(s) } else { ' ' };
+    let horizontal = |s| format!(" {} ", segment(s).to_string().repeat(4));
+    let vertical = |l, r| format!("{}    {}", segment(l), segment(r));
+    vec![
+        horizontal(0),
+        vertical(1, 2),
+        vertical(1, 2),
+        horizontal(3),
+        vertical(4, 5),
+        vertical(4, 5),
+        horizontal(6),
+    ]
+}
+
+fn side_by_side(digits: &[Vec<String>]) -> String {
+    (0..7)
+        .map(|row| digits.iter().map(|art| art[row].as_str()).join("  "))
+        .map(|line| line.trim_end().to_owned() + "\n")
+        .collect()
+}
+
+impl SegmentDisplay<'static> {
+    fn deduce_from(patterns: &str) -> Result<Self, DecodeError> {
+        Self::deduce_with(&SEVEN_SEGMENT, patterns)
+    }
+
+    /// Like `render_trace` but for patterns that may not decode, only showing what
+    /// follows from the patterns before any guessing.
+    fn explain(patterns: &str) -> Result<String, DecodeError> {
+        let patterns = patterns
+            .split_ascii_whitespace()
+            .map(|p| SEVEN_SEGMENT.to_mask(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut constraints = Constraints::new(&SEVEN_SEGMENT, patterns);
+        constraints.propagate();
+        Ok(render_steps(&named(&constraints.trace)))
+    }
+}
+
+fn named(trace: &[Vec<(usize, usize)>]) -> Vec<Vec<(char, usize)>> {
+    trace
+        .iter()
+        .map(|step| step.iter().map(|(w, s)| (wire_name(*w), *s)).collect())
+        .collect()
+}
+
+fn render_steps(trace: &[Vec<(char, usize)>]) -> String {
+    let mut known = HashMap::new();
+    let steps = trace
+        .iter()
+        .map(|step| {
+            known.extend(step.iter().map(|(wire, segment)| (*segment, *wire)));
+            let lit = known.keys().fold(0, |mask, s| mask | 1 << s);
+            seven_segment_art(lit, |s| match step.iter().any(|(_, seg)| *seg == s) {
+                true => known[&s].to_ascii_uppercase(),
+                false => known[&s],
+            })
+        })
+        .collect::<Vec<_>>();
+    side_by_side(&steps)
+}
+
+impl<'a> SegmentDisplay<'a> {
+    /// Works out the wiring from any number of distinct or repeated patterns.
+    fn deduce_with(layout: &'a Layout, patterns: &str) -> Result<Self, DecodeError> {
+        let patterns = patterns
+            .split_ascii_whitespace()
+            .map(|p| layout.to_mask(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut solutions = Vec::new();
+        Constraints::new(layout, patterns).search(&mut solutions);
+        match solutions.as_slice() {
+            [] => Err(DecodeError::Inconsistent),
+            [solution] => {
+                let wiring = solution
+                    .domains
+                    .iter()
+                    .enumerate()
+                    .map(|(w, domain)| (wire_name(w), domain.trailing_zeros() as usize))
+                    .collect();
+                let trace = named(&solution.trace);
+                Ok(Self {
+                    layout,
+                    wiring,
+                    trace,
+                })
             }
-        });
-        let unknown = pattern
-            .chars()
-            .find(|c| self.uniq.get(c).is_none())
-            .unwrap();
-        self.uniq.insert(unknown, unknown_val);
+            _ => Err(DecodeError::Ambiguous),
+        }
+    }
+
+    /// Segments lit by a scrambled pattern.
+    fn lit(&self, pattern: &str) -> Result<u32, DecodeError> {
+        pattern.chars().try_fold(0, |mask, c| {
+            let segment = self.wiring.get(&c).ok_or(DecodeError::UnknownWire(c))?;
+            Ok(mask | 1 << segment)
+        })
     }
 
-    fn signal(&self, signal: &str) -> String {
+    /// Draws the output twice: each segment labelled with the scrambled wire driving it,
+    /// then with its own name.
+    fn render(&self, signal: &str) -> Result<String, DecodeError> {
+        if self.layout.segments != 7 {
+            return Err(DecodeError::NotSevenSegment);
+        }
+        let wire_of = |s| self.wiring.iter().find(|(_, seg)| **seg == s).unwrap().0;
+        let (scrambled, decoded): (Vec<_>, Vec<_>) = signal
+            .split_ascii_whitespace()
+            .map(|sigd| {
+                let lit = self.lit(sigd)?;
+                Ok((
+                    seven_segment_art(lit, |s| *wire_of(s)),
+                    seven_segment_art(lit, wire_name),
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .unzip();
+        Ok(side_by_side(&scrambled) + "\n" + &side_by_side(&decoded))
+    }
+
+    /// Draws a digit 8 for each step of the deduction. Segments are labelled with
+    /// the wire driving them, upper case for the ones found in that step.
+    fn render_trace(&self) -> Result<String, DecodeError> {
+        if self.layout.segments != 7 {
+            return Err(DecodeError::NotSevenSegment);
+        }
+        Ok(render_steps(&self.trace))
+    }
+
+    fn signal(&self, signal: &str) -> Result<String, DecodeError> {
         signal
             .split_ascii_whitespace()
-            .map(|sigd| -> String {
-                let input = sigd
-                    .chars()
-                    .map(|c| *self.uniq.get(&c).unwrap())
-                    .collect::<BTreeSet<_>>();
-                format!("{}", *SIGNAL_MAP.get(&input).unwrap())
+            .map(|sigd| {
+                let lit = self.lit(sigd)?;
+                self.layout
+                    .glyph(lit)
+                    .ok_or_else(|| DecodeError::UnknownGlyph(sigd.to_owned()))
             })
             .collect()
     }
 }
+
 fn main() {
     let signals = get_patterns_output("input/08.txt");
     println!("part 1: {}", count_easy_digits(&signals));
@@ -124,13 +480,22 @@ fn get_patterns_output(p: impl AsRef<Path>) -> Vec<(String, String)> {
         .collect()
 }
 
+fn decode(patterns: &str, output: &str) -> String {
+    let disp = SegmentDisplay::deduce_from(patterns).unwrap_or_else(|e| {
+        let steps = SegmentDisplay::explain(patterns).unwrap_or_default();
+        panic!("{:?} wiring for {}\n{}", e, patterns, steps)
+    });
+    disp.signal(output).unwrap_or_else(|e| {
+        let wiring = disp.render_trace().unwrap_or_default();
+        let art = disp.render(output).unwrap_or_default();
+        panic!("{:?} in {}\n{}\n{}", e, output, wiring, art)
+    })
+}
+
 fn output_sum(input: &[(String, String)]) -> usize {
     input
         .iter()
-        .map(|(patterns, output)| {
-            let disp = SegmentDisplay::deduce_from(patterns);
-            disp.signal(output).parse::<usize>().unwrap()
-        })
+        .map(|(patterns, output)| decode(patterns, output).parse::<usize>().unwrap())
         .sum()
 }
 
@@ -138,8 +503,7 @@ fn count_easy_digits(input: &[(String, String)]) -> usize {
     input
         .iter()
         .map(|(patterns, output)| {
-            let disp = SegmentDisplay::deduce_from(patterns);
-            disp.signal(output)
+            decode(patterns, output)
                 .chars()
                 .filter(|c| ['1', '4', '7', '8'].contains(c))
                 .collect::<String>()
@@ -162,7 +526,121 @@ mod problem08 {
     fn part2() {
         let display = SegmentDisplay::deduce_from(
             "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab",
+        )
+        .unwrap();
+        assert_eq!(display.signal("cdfeb fcadb cdfeb cdbaf").unwrap(), "5353");
+    }
+
+    #[test]
+    fn subset_of_patterns() {
+        // 8, 7 and 1 only pin down the wire for a
+        let display = SegmentDisplay::deduce_from("acedgfb dab ab");
+        assert_eq!(display.unwrap_err(), DecodeError::Ambiguous);
+
+        // patterns for 5, 2, 7 and 4 are enough
+        let display = SegmentDisplay::deduce_from("cdfbe gcdfa dab eafb").unwrap();
+        assert_eq!(display.signal("cdfeb fcadb cdfeb cdbaf").unwrap(), "5353");
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        // two patterns with two segments each
+        let display = SegmentDisplay::deduce_from("ab cd");
+        assert_eq!(display.unwrap_err(), DecodeError::Inconsistent);
+        // there are only seven wires
+        let display = SegmentDisplay::deduce_from("abcdefgh");
+        assert_eq!(display.unwrap_err(), DecodeError::UnknownWire('h'));
+
+        let display = SegmentDisplay::deduce_from(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab",
+        )
+        .unwrap();
+        assert_eq!(
+            display.signal("abcf").unwrap_err(),
+            DecodeError::UnknownGlyph("abcf".to_owned())
         );
-        assert_eq!(display.signal("cdfeb fcadb cdfeb cdbaf"), "5353");
+    }
+
+    #[test]
+    fn alphanumeric_displays() {
+        for layout in [&*FOURTEEN_SEGMENT, &*SIXTEEN_SEGMENT] {
+            let lit = layout.glyphs.iter().map(|(_, lit)| *lit);
+            assert_eq!(lit.unique().count(), layout.glyphs.len());
+
+            // segment `s` is driven by wire `(5s + 3) mod n`
+            let n = layout.segments;
+            let scramble = |text: &str| {
+                text.chars()
+                    .map(|glyph| {
+                        let (_, lit) = layout.glyphs.iter().find(|(g, _)| *g == glyph).unwrap();
+                        layout
+                            .bits(*lit)
+                            .map(|segment| (b'a' + ((5 * segment + 3) % n) as u8) as char)
+                            .collect::<String>()
+                    })
+                    .join(" ")
+            };
+
+            let glyphs = layout
+                .glyphs
+                .iter()
+                .map(|(glyph, _)| glyph)
+                .collect::<String>();
+            let patterns = scramble(&glyphs);
+            let display = SegmentDisplay::deduce_with(layout, &patterns).unwrap();
+            assert_eq!(display.signal(&scramble("HELLO2021")).unwrap(), "HELLO2021");
+            let render = display.render(&scramble("HI"));
+            assert_eq!(render, Err(DecodeError::NotSevenSegment));
+            assert_eq!(display.render_trace(), Err(DecodeError::NotSevenSegment));
+
+            let display = SegmentDisplay::deduce_with(layout, &scramble("HI"));
+            assert_eq!(display.unwrap_err(), DecodeError::Ambiguous);
+        }
+    }
+
+    #[test]
+    fn render() {
+        let display = SegmentDisplay::deduce_from(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab",
+        )
+        .unwrap();
+        let expected = [
+            " dddd    dddd",
+            "e            a",
+            "e            a",
+            " ffff    ffff",
+            "     b  g",
+            "     b  g",
+            " cccc    cccc",
+            "",
+            " aaaa    aaaa",
+            "b            c",
+            "b            c",
+            " dddd    dddd",
+            "     f  e",
+            "     f  e",
+            " gggg    gggg",
+            "",
+        ];
+        assert_eq!(display.render("cdfeb gcdfa").unwrap(), expected.join("\n"));
+
+        let expected = [
+            "                 DDDD    dddd",
+            "E       e    A  e    a  e    a",
+            "E       e    A  e    a  e    a",
+            "                         FFFF",
+            "G       g    B  g    b  g    b",
+            "G       g    B  g    b  g    b",
+            "                 CCCC    cccc",
+            "",
+        ];
+        assert_eq!(display.render_trace().unwrap(), expected.join("\n"));
+    }
+
+    #[test]
+    fn explain() {
+        // only 1 and 7, so only the wire for segment a is known
+        let steps = SegmentDisplay::explain("dab ab").unwrap();
+        assert_eq!(steps, " DDDD\n\n\n\n\n\n\n");
     }
 }
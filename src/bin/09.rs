@@ -1,19 +1,16 @@
-use std::{collections::HashSet, path::Path};
+use std::path::Path;
 
 use itertools::Itertools;
 
 fn main() {
     let heightmap = get_height_map("input/09.txt");
-    let low_points = low_points(&heightmap);
+    let low_points = low_points(&heightmap, &Watershed::default());
     let risk_levels = low_points.iter().map(|(p, _)| p + 1);
     println!("part 1: {}", risk_levels.sum::<u32>());
 
-    let basins = basins(&heightmap);
-    let three_largest = basins.iter().sorted_by_key(|b| b.len()).rev().take(3);
-    println!(
-        "part 2: {}",
-        three_largest.map(|v| v.len()).product::<usize>()
-    );
+    let basins = label_basins(&heightmap, &Watershed::default());
+    let three_largest = basins.basins.iter().map(|b| b.size).sorted().rev().take(3);
+    println!("part 2: {}", three_largest.product::<usize>());
 }
 
 fn get_height_map(p: impl AsRef<Path>) -> Vec<Vec<u32>> {
@@ -24,34 +21,72 @@ fn get_height_map(p: impl AsRef<Path>) -> Vec<Vec<u32>> {
         .collect()
 }
 
-fn neighbours(map: &[Vec<u32>], row: usize, col: usize) -> Vec<(u32, (usize, usize))> {
-    let mut neighbours = Vec::new();
-    // up
-    if let Some(r) = row.checked_sub(1) {
-        neighbours.push((map[r][col], (r, col)));
-    }
-    // down
-    if row + 1 < map.len() {
-        neighbours.push((map[row + 1][col], (row + 1, col)));
+/// How cells are grouped into basins. The default follows the puzzle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Watershed {
+    /// cells at least this high are walls between basins
+    wall: u32,
+    /// whether diagonally adjacent cells are neighbours
+    diagonal: bool,
+    /// whether a low point has to be strictly lower than all its neighbours,
+    /// otherwise lower or equal
+    strict: bool,
+}
+
+impl Default for Watershed {
+    fn default() -> Self {
+        Self {
+            wall: 9,
+            diagonal: false,
+            strict: true,
+        }
     }
-    // left
-    if let Some(c) = col.checked_sub(1) {
-        neighbours.push((map[row][c], (row, c)));
+}
+
+impl Watershed {
+    fn neighbour_count(&self) -> usize {
+        match self.diagonal {
+            true => 8,
+            false => 4,
+        }
     }
-    // right
-    if col + 1 < map[0].len() {
-        neighbours.push((map[row][col + 1], (row, col + 1)));
+
+    fn neighbours(&self, map: &[Vec<u32>], row: usize, col: usize) -> Vec<(u32, (usize, usize))> {
+        // up, down, left, right, then the corners
+        const OFFSETS: [(isize, isize); 8] = [
+            (-1, 0),
+            (1, 0),
+            (0, -1),
+            (0, 1),
+            (-1, -1),
+            (-1, 1),
+            (1, -1),
+            (1, 1),
+        ];
+        OFFSETS[..self.neighbour_count()]
+            .iter()
+            .filter_map(|(dr, dc)| {
+                let r = row.checked_add_signed(*dr).filter(|r| *r < map.len())?;
+                let c = col.checked_add_signed(*dc).filter(|c| *c < map[r].len())?;
+                Some((map[r][c], (r, c)))
+            })
+            .collect()
     }
-    neighbours
 }
 
-fn low_points(map: &[Vec<u32>]) -> Vec<(u32, (usize, usize))> {
+/// Cells lower than their neighbours that aren't walls.
+fn low_points(map: &[Vec<u32>], watershed: &Watershed) -> Vec<(u32, (usize, usize))> {
     map.iter()
         .enumerate()
-        .map(move |(i, row)| row.iter().enumerate().map(move |(j, c)| (i, j, *c)))
-        .flatten()
+        .flat_map(move |(i, row)| row.iter().enumerate().map(move |(j, c)| (i, j, *c)))
+        .filter(|(_, _, val)| *val < watershed.wall)
         .filter_map(|(row, col, val)| {
-            match neighbours(map, row, col).iter().all(|(n, _)| *n > val) {
+            let neighbours = watershed.neighbours(map, row, col);
+            let lower = |n: &u32| match watershed.strict {
+                true => *n > val,
+                false => *n >= val,
+            };
+            match neighbours.iter().all(|(n, _)| lower(n)) {
                 true => Some((val, (row, col))),
                 false => None,
             }
@@ -59,54 +94,122 @@ fn low_points(map: &[Vec<u32>]) -> Vec<(u32, (usize, usize))> {
         .collect()
 }
 
-fn basins(map: &[Vec<u32>]) -> Vec<HashSet<(usize, usize)>> {
-    fn basin(
-        map: &[Vec<u32>],
-        pos: &(usize, usize),
-        visited: &mut HashSet<(usize, usize)>,
-    ) -> HashSet<(usize, usize)> {
-        let pos_height = map[pos.0][pos.1];
-        let neighbours = neighbours(map, pos.0, pos.1);
-        let filtered_neighbours = neighbours
-            .into_iter()
-            .filter(|(h, pos)| *h > pos_height && !visited.contains(pos))
-            .collect::<Vec<_>>();
-
-        let basin = filtered_neighbours
-            .iter()
-            .map(|(h, pos)| {
-                visited.insert(pos.to_owned());
-                match h {
-                    9 => HashSet::new(),
-                    _ => basin(map, pos, visited),
-                }
-            })
-            .flatten()
-            .chain(std::iter::once(pos.to_owned()))
-            .collect::<HashSet<_>>();
+/// Disjoint sets of cells, indexed row by row.
+struct UnionFind {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
 
-        basin
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        Self {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+        }
     }
 
-    let low_points = low_points(map);
-    let mut visited = HashSet::new();
-    low_points
-        .iter()
-        .map(|(_, pos)| basin(map, pos, &mut visited))
-        .collect()
+    fn find(&mut self, mut x: usize) -> usize {
+        while self.parent[x] != x {
+            self.parent[x] = self.parent[self.parent[x]];
+            x = self.parent[x];
+        }
+        x
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        if self.size[a] < self.size[b] {
+            std::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Basin {
+    size: usize,
+    /// lowest cell of the basin, the first one row by row if there are several
+    low_point: (usize, usize),
+    /// cells of the basin next to a wall or the edge of the map
+    boundary: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+struct Basins {
+    /// basin id of every cell, `None` for walls
+    labels: Vec<Vec<Option<usize>>>,
+    basins: Vec<Basin>,
+}
+
+/// Every connected area of cells that aren't walls is a basin, no matter how many low
+/// points it has. Basins are numbered in the order their first cell appears.
+fn label_basins(map: &[Vec<u32>], watershed: &Watershed) -> Basins {
+    let (height, width) = (map.len(), map.first().map_or(0, Vec::len));
+    let mut sets = UnionFind::new(height * width);
+    for (row, col) in (0..height).cartesian_product(0..width) {
+        if map[row][col] >= watershed.wall {
+            continue;
+        }
+        for (h, (r, c)) in watershed.neighbours(map, row, col) {
+            if h < watershed.wall {
+                sets.union(row * width + col, r * width + c);
+            }
+        }
+    }
+
+    let mut labels = vec![vec![None; width]; height];
+    let mut ids = vec![None; height * width];
+    let mut basins: Vec<Basin> = Vec::new();
+    for (row, col) in (0..height).cartesian_product(0..width) {
+        let h = map[row][col];
+        if h >= watershed.wall {
+            continue;
+        }
+        let root = sets.find(row * width + col);
+        let id = *ids[root].get_or_insert_with(|| {
+            basins.push(Basin {
+                size: 0,
+                low_point: (row, col),
+                boundary: Vec::new(),
+            });
+            basins.len() - 1
+        });
+        labels[row][col] = Some(id);
+
+        let basin = &mut basins[id];
+        basin.size += 1;
+        let (r, c) = basin.low_point;
+        if h < map[r][c] {
+            basin.low_point = (row, col);
+        }
+        let neighbours = watershed.neighbours(map, row, col);
+        let at_edge = neighbours.len() < watershed.neighbour_count();
+        if at_edge || neighbours.iter().any(|(h, _)| *h >= watershed.wall) {
+            basin.boundary.push((row, col));
+        }
+    }
+
+    Basins { labels, basins }
 }
 
 #[allow(dead_code)]
-fn debug_print_basin(basin: &HashSet<(usize, usize)>, map: &[Vec<u32>]) {
-    let map_width = map[0].len();
-    let map_height = map.len();
-    (0..map_height).for_each(|row| {
-        (0..map_width).for_each(|col| match basin.contains(&(row, col)) {
-            true => print!("\x1b[93m{}\x1b[0m", map[row][col]),
-            false => print!("{}", map[row][col]),
+fn debug_print_basin(basins: &Basins, id: usize, map: &[Vec<u32>]) {
+    map.iter()
+        .zip(&basins.labels)
+        .for_each(|(heights, labels)| {
+            heights
+                .iter()
+                .zip(labels)
+                .for_each(|(h, label)| match *label == Some(id) {
+                    true => print!("\x1b[93m{}\x1b[0m", h),
+                    false => print!("{}", h),
+                });
+            println!();
         });
-        println!();
-    });
     println!();
 }
 
@@ -117,7 +220,7 @@ mod problem09 {
     #[test]
     fn part1() {
         let heightmap = get_height_map("input/09.test.txt");
-        let low_points = low_points(&heightmap);
+        let low_points = low_points(&heightmap, &Watershed::default());
         let risk_levels = low_points.iter().map(|(p, _)| p + 1);
         assert_eq!(risk_levels.sum::<u32>(), 15);
     }
@@ -125,8 +228,77 @@ mod problem09 {
     #[test]
     fn part2() {
         let heightmap = get_height_map("input/09.test.txt");
-        let basins = basins(&heightmap);
-        let three_largest = basins.iter().sorted_by_key(|b| b.len()).rev().take(3);
-        assert_eq!(1134, three_largest.map(|v| v.len()).product::<usize>());
+        let basins = label_basins(&heightmap, &Watershed::default());
+        let three_largest = basins.basins.iter().map(|b| b.size).sorted().rev().take(3);
+        assert_eq!(1134, three_largest.product::<usize>());
+    }
+
+    #[test]
+    fn basin_labels() {
+        let heightmap = get_height_map("input/09.test.txt");
+        let Basins { labels, basins } = label_basins(&heightmap, &Watershed::default());
+        assert_eq!(basins.len(), 4);
+        assert_eq!(labels[0][0], Some(0));
+        assert_eq!(labels[0][2], None);
+        assert_eq!(labels[4][9], Some(3));
+        assert_eq!(
+            basins[0],
+            Basin {
+                size: 3,
+                low_point: (0, 1),
+                boundary: vec![(0, 0), (0, 1), (1, 0)],
+            }
+        );
+        let low_points = low_points(&heightmap, &Watershed::default());
+        assert!(basins
+            .iter()
+            .all(|b| low_points.iter().any(|(_, p)| *p == b.low_point)));
+
+        // a plateau and a basin with two minima
+        let heightmap = vec![
+            vec![5, 5, 9, 1, 3],
+            vec![5, 5, 9, 4, 9],
+            vec![9, 9, 9, 2, 3],
+        ];
+        let Basins { labels, basins } = label_basins(&heightmap, &Watershed::default());
+        assert_eq!(basins.len(), 2);
+        assert_eq!((basins[0].size, basins[0].low_point), (4, (0, 0)));
+        assert_eq!((basins[1].size, basins[1].low_point), (5, (0, 3)));
+        assert_eq!(labels[2][4], Some(1));
+
+        let Basins { labels, basins } = label_basins(&[], &Watershed::default());
+        assert!(labels.is_empty() && basins.is_empty());
+    }
+
+    #[test]
+    fn watershed() {
+        let heightmap = get_height_map("input/09.test.txt");
+
+        // 8s become walls as well, shrinking the middle and bottom right basins
+        let walls = Watershed {
+            wall: 8,
+            ..Watershed::default()
+        };
+        let basins = label_basins(&heightmap, &walls).basins;
+        let sizes = basins.iter().map(|b| b.size).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![3, 9, 7, 6]);
+
+        // diagonals leak through every wall, joining all basins
+        let diagonal = Watershed {
+            diagonal: true,
+            ..Watershed::default()
+        };
+        let basins = label_basins(&heightmap, &diagonal).basins;
+        let sizes = basins.iter().map(|b| b.size).collect::<Vec<_>>();
+        assert_eq!(sizes, vec![35]);
+        assert_eq!(low_points(&heightmap, &diagonal).len(), 4);
+
+        let plateau = vec![vec![1, 1, 2], vec![3, 1, 2]];
+        assert!(low_points(&plateau, &Watershed::default()).is_empty());
+        let lower_or_equal = Watershed {
+            strict: false,
+            ..Watershed::default()
+        };
+        assert_eq!(low_points(&plateau, &lower_or_equal).len(), 3);
     }
 }
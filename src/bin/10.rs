@@ -1,199 +1,535 @@
-use std::{fmt::Display, path::Path, str::FromStr};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
 
 use itertools::Itertools;
 
+/// A pair of open and close tokens, with the points for a corrupted line ending in the
+/// close token and for completing a line with it.
+#[derive(Clone, Debug, PartialEq)]
+struct Delimiter {
+    open: String,
+    close: String,
+    corruption: usize,
+    completion: usize,
+}
+
+impl Delimiter {
+    fn new(open: &str, close: &str, corruption: usize, completion: usize) -> Self {
+        Self {
+            open: open.to_string(),
+            close: close.to_string(),
+            corruption,
+            completion,
+        }
+    }
+}
+
+/// The delimiters of a bracket language. Tokens may be longer than one character, but
+/// none may be empty or appear twice.
+#[derive(Clone, Debug)]
+struct DelimiterSet {
+    pairs: Vec<Delimiter>,
+}
+
+impl Default for DelimiterSet {
+    /// The brackets of the navigation subsystem.
+    fn default() -> Self {
+        Self::new(vec![
+            Delimiter::new("(", ")", 3, 1),
+            Delimiter::new("[", "]", 57, 2),
+            Delimiter::new("{", "}", 1197, 3),
+            Delimiter::new("<", ">", 25137, 4),
+        ])
+        .unwrap()
+    }
+}
+
+impl DelimiterSet {
+    fn new(pairs: Vec<Delimiter>) -> Result<Self, ()> {
+        let texts = pairs
+            .iter()
+            .flat_map(|d| [d.open.as_str(), d.close.as_str()])
+            .collect::<Vec<_>>();
+        if texts.iter().any(|t| t.is_empty()) || !texts.iter().all_unique() {
+            return Err(());
+        }
+        Ok(Self { pairs })
+    }
+
+    /// Tokens of line number `line` together with their column, both counted from 1.
+    /// Whitespace between tokens is skipped, and the longest token wins.
+    fn tokenize_line<'a>(
+        &'a self,
+        line: usize,
+        s: &str,
+    ) -> Result<Vec<(usize, Token<'a>)>, SyntaxError<'a>> {
+        let mut tokens = Vec::new();
+        let mut rest = s;
+        let mut column = 1;
+        while let Some(c) = rest.chars().next() {
+            if c.is_whitespace() {
+                rest = &rest[c.len_utf8()..];
+                column += 1;
+                continue;
+            }
+            let token = self
+                .pairs
+                .iter()
+                .flat_map(|d| [Token::Open(d), Token::Close(d)])
+                .filter(|token| rest.starts_with(token.text()))
+                .max_by_key(|token| token.text().len())
+                .ok_or_else(|| SyntaxError::new(line, column, ErrorKind::UnknownChar(c)))?;
+            tokens.push((column, token));
+            rest = &rest[token.text().len()..];
+            column += token.width();
+        }
+        Ok(tokens)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum Token {
-    OpenBraces,
-    OpenBrackets,
-    OpenParenthesis,
-    OpenAngleBrackets,
-    CloseBraces,
-    CloseBrackets,
-    CloseParenthesis,
-    CloseAngleBrackets,
-}
-
-impl FromStr for Token {
-    type Err = ();
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "{" => Ok(Self::OpenBraces),
-            "[" => Ok(Self::OpenBrackets),
-            "(" => Ok(Self::OpenParenthesis),
-            "<" => Ok(Self::OpenAngleBrackets),
-            "}" => Ok(Self::CloseBraces),
-            "]" => Ok(Self::CloseBrackets),
-            ")" => Ok(Self::CloseParenthesis),
-            ">" => Ok(Self::CloseAngleBrackets),
-            _ => Err(()),
-        }
-    }
-}
-
-impl Display for Token {
+enum Token<'a> {
+    Open(&'a Delimiter),
+    Close(&'a Delimiter),
+}
+
+impl Display for Token<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::OpenBraces => "{",
-                Self::OpenBrackets => "[",
-                Self::OpenParenthesis => "(",
-                Self::OpenAngleBrackets => "<",
-                Self::CloseBraces => "}",
-                Self::CloseBrackets => "]",
-                Self::CloseParenthesis => ")",
-                Self::CloseAngleBrackets => ">",
-            }
-        )
+        write!(f, "{}", self.text())
     }
 }
 
-impl Token {
+impl<'a> Token<'a> {
     fn is_open(&self) -> bool {
-        matches!(
-            self,
-            &Self::OpenBraces
-                | &Self::OpenBrackets
-                | &Self::OpenParenthesis
-                | &Self::OpenAngleBrackets
-        )
+        matches!(self, Self::Open(_))
     }
 
-    fn matching_close(&self) -> Result<Self, ()> {
-        if self.is_open() {
-            Ok(match self {
-                Self::OpenBraces => Self::CloseBraces,
-                Self::OpenBrackets => Self::CloseBrackets,
-                Self::OpenParenthesis => Self::CloseParenthesis,
-                Self::OpenAngleBrackets => Self::CloseAngleBrackets,
-                _ => unreachable!(),
-            })
-        } else {
-            Err(())
+    fn delimiter(&self) -> &'a Delimiter {
+        match self {
+            Self::Open(d) | Self::Close(d) => d,
+        }
+    }
+
+    fn text(&self) -> &'a str {
+        match self {
+            Self::Open(d) => &d.open,
+            Self::Close(d) => &d.close,
         }
     }
 
-    fn tokenize_line(s: &str) -> Vec<Self> {
-        s.chars().map(|c| c.to_string().parse().unwrap()).collect()
+    /// Number of characters in the token.
+    fn width(&self) -> usize {
+        self.text().chars().count()
+    }
+
+    fn matching_close(&self) -> Result<Self, ()> {
+        match self {
+            Self::Open(d) => Ok(Self::Close(d)),
+            Self::Close(_) => Err(()),
+        }
     }
 }
 
-#[derive(Copy, Clone, Debug)]
-struct SyntaxError {
-    expected: Option<Token>,
-    found: Token,
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ErrorKind<'a> {
+    /// a closing token that doesn't match the innermost open one, `opener` is the
+    /// column of that open token
+    Unexpected {
+        expected: Option<Token<'a>>,
+        found: Token<'a>,
+        opener: Option<usize>,
+    },
+    UnknownChar(char),
 }
 
-impl SyntaxError {
-    fn new(expected: Option<Token>, found: Token) -> Self {
-        Self { expected, found }
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct SyntaxError<'a> {
+    line: usize,
+    column: usize,
+    kind: ErrorKind<'a>,
+}
+
+impl<'a> SyntaxError<'a> {
+    fn new(line: usize, column: usize, kind: ErrorKind<'a>) -> Self {
+        Self { line, column, kind }
     }
 
     fn points(&self) -> usize {
-        match &self.found {
-            Token::CloseBraces => 1197,
-            Token::CloseBrackets => 57,
-            Token::CloseParenthesis => 3,
-            Token::CloseAngleBrackets => 25137,
-            _ => unreachable!(),
+        match &self.kind {
+            ErrorKind::Unexpected { found, .. } => found.delimiter().corruption,
+            ErrorKind::UnknownChar(_) => 0,
         }
     }
+
+    /// Shows the error under its line `s` like a compiler does, marking the unmatched
+    /// opener with `-` and the offending token with `^`.
+    fn render(&self, s: &str) -> String {
+        let gutter = " ".repeat(self.line.to_string().len());
+        let mut marks = vec![' '; self.column - 1];
+        match self.kind {
+            ErrorKind::Unexpected {
+                expected: Some(expected),
+                found,
+                opener: Some(opener),
+            } => {
+                let opener_width = expected.delimiter().open.chars().count();
+                marks.splice(
+                    opener - 1..opener - 1 + opener_width,
+                    vec!['-'; opener_width],
+                );
+                marks.extend(vec!['^'; found.width()]);
+            }
+            ErrorKind::Unexpected { found, .. } => marks.extend(vec!['^'; found.width()]),
+            ErrorKind::UnknownChar(_) => marks.push('^'),
+        }
+        format!(
+            "error: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}",
+            self,
+            gutter,
+            self.line,
+            self.column,
+            gutter,
+            self.line,
+            s,
+            gutter,
+            marks.into_iter().collect::<String>()
+        )
+    }
 }
 
-impl Display for SyntaxError {
+impl Display for SyntaxError<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Expected {}, but found {}",
-            self.expected
-                .map_or(String::from("no token"), |t| t.to_string()),
-            self.found
-        )
+        match self.kind {
+            ErrorKind::Unexpected {
+                expected, found, ..
+            } => write!(
+                f,
+                "Expected {}, but found {}",
+                expected.map_or(String::from("no token"), |t| t.to_string()),
+                found
+            ),
+            ErrorKind::UnknownChar(c) => write!(f, "Unknown character {:?}", c),
+        }
     }
 }
 
+/// What a single pass over a line finds.
+#[derive(Clone, Debug, PartialEq)]
+enum Analysis<'a> {
+    Valid,
+    Corrupted(SyntaxError<'a>),
+    /// close tokens that complete the line, innermost first
+    Incomplete(Vec<Token<'a>>),
+}
+
 struct SyntaxChecker;
 
 impl SyntaxChecker {
-    fn check_line(s: &str) -> Option<SyntaxError> {
-        let mut stack = Vec::new();
-        let tokens = Token::tokenize_line(s);
-        for token in tokens {
+    fn analyse_line<'a>(delimiters: &'a DelimiterSet, line: usize, s: &str) -> Analysis<'a> {
+        let mut stack: Vec<(usize, Token)> = Vec::new();
+        let tokens = match delimiters.tokenize_line(line, s) {
+            Ok(tokens) => tokens,
+            Err(error) => return Analysis::Corrupted(error),
+        };
+        for (column, token) in tokens {
             if token.is_open() {
-                stack.push(token);
-            } else if let Some(top) = stack.last() {
+                stack.push((column, token));
+            } else if let Some((opener, top)) = stack.last() {
                 let matching_close = top.matching_close().ok();
-                if matching_close.map_or(false, |matching| matching == token) {
+                if matching_close == Some(token) {
                     stack.pop();
                 } else {
-                    return Some(SyntaxError::new(matching_close, token));
+                    let kind = ErrorKind::Unexpected {
+                        expected: matching_close,
+                        found: token,
+                        opener: Some(*opener),
+                    };
+                    return Analysis::Corrupted(SyntaxError::new(line, column, kind));
                 }
             } else {
-                return Some(SyntaxError::new(None, token));
+                let kind = ErrorKind::Unexpected {
+                    expected: None,
+                    found: token,
+                    opener: None,
+                };
+                return Analysis::Corrupted(SyntaxError::new(line, column, kind));
             }
         }
-        None
+        match stack.is_empty() {
+            true => Analysis::Valid,
+            false => Analysis::Incomplete(
+                stack
+                    .iter()
+                    .rev()
+                    .map(|(_, token)| token.matching_close().unwrap())
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Analyses every line of `reader` as it is read, numbering lines from 1. Lines that
+    /// cannot be read, e.g. because they are not valid UTF-8, come out as errors.
+    fn analyse_reader<'a>(
+        delimiters: &'a DelimiterSet,
+        reader: impl BufRead + 'a,
+    ) -> impl Iterator<Item = io::Result<Analysis<'a>>> + 'a {
+        reader
+            .lines()
+            .enumerate()
+            .map(move |(i, s)| s.map(|s| Self::analyse_line(delimiters, i + 1, &s)))
     }
-}
 
-struct AutoComplete;
+    /// Keeps going past corrupted tokens, reporting every one of them together with
+    /// the fewest deletions and insertions that make the line valid and complete.
+    /// The fewest edits are only guaranteed for lines whose corrupted part is short,
+    /// see `pair_tokens`.
+    fn recover_line<'a>(
+        delimiters: &'a DelimiterSet,
+        line: usize,
+        s: &str,
+    ) -> Result<Recovery<'a>, SyntaxError<'a>> {
+        let tokens = delimiters.tokenize_line(line, s)?;
+        let partner = pair_tokens(&tokens);
+        let unexpected = |stack: &[usize], column, found| {
+            let top = stack.last().map(|top| tokens[*top]);
+            let kind = ErrorKind::Unexpected {
+                expected: top.map(|(_, open)| open.matching_close().unwrap()),
+                found,
+                opener: top.map(|(opener, _)| opener),
+            };
+            SyntaxError::new(line, column, kind)
+        };
 
-impl AutoComplete {
-    fn check_line(s: &str) -> String {
+        let mut recovery = Recovery {
+            errors: Vec::new(),
+            edits: Vec::new(),
+        };
         let mut stack = Vec::new();
-        let tokens = Token::tokenize_line(s);
-        for token in tokens {
+        for (i, (column, token)) in tokens.iter().copied().enumerate() {
             if token.is_open() {
-                stack.push(token);
-            } else if let Some(top) = stack.last() {
-                let matching_close = top.matching_close().ok();
-                if matching_close.map_or(false, |matching| matching == token) {
-                    stack.pop();
-                } else {
-                    unreachable!("illegal line, filter with syntax checker");
+                stack.push(i);
+                continue;
+            }
+            let p = match partner[i] {
+                Some(p) => p,
+                None => {
+                    recovery.errors.push(unexpected(&stack, column, token));
+                    recovery.edits.push(Edit::Delete { column, token });
+                    continue;
+                }
+            };
+            if stack.last() != Some(&p) {
+                recovery.errors.push(unexpected(&stack, column, token));
+            }
+            while let Some(open) = stack.pop().filter(|open| *open != p) {
+                let token = tokens[open].1.matching_close().unwrap();
+                recovery.edits.push(Edit::Insert { column, token });
+            }
+        }
+        let column = s.chars().count() + 1;
+        while let Some(open) = stack.pop() {
+            let token = tokens[open].1.matching_close().unwrap();
+            recovery.edits.push(Edit::Insert { column, token });
+        }
+        Ok(recovery)
+    }
+}
+
+/// Longest stretch of unpaired tokens `best_matching` is run on, which takes cubic
+/// time and quadratic memory in its length.
+const MINIMAL_RECOVERY_LIMIT: usize = 500;
+
+/// Pairs up open and close tokens with pairs nested properly, leaving as few unpaired
+/// as possible. Adjacent matching tokens can always be paired without losing anything,
+/// so those are paired up like a stack would first, and `best_matching` only runs on
+/// the tokens left between the first open and the last close one. Beyond
+/// `MINIMAL_RECOVERY_LIMIT` tokens, each close token is paired with the innermost
+/// matching open token instead, which isn't always the fewest unpaired.
+fn pair_tokens(tokens: &[(usize, Token)]) -> Vec<Option<usize>> {
+    let mut partner = vec![None; tokens.len()];
+    let mut left: Vec<usize> = Vec::new();
+    for (i, (_, token)) in tokens.iter().enumerate() {
+        match left.last() {
+            Some(top) if tokens[*top].1.matching_close() == Ok(*token) => {
+                partner[i] = Some(*top);
+                partner[*top] = Some(i);
+                left.pop();
+            }
+            _ => left.push(i),
+        }
+    }
+
+    // close tokens before any open one and open tokens after the last close one stay unpaired
+    let first_open = left.iter().position(|i| tokens[*i].1.is_open());
+    let last_close = left.iter().rposition(|i| !tokens[*i].1.is_open());
+    let left = match (first_open, last_close) {
+        (Some(first), Some(last)) if first < last => &left[first..=last],
+        _ => return partner,
+    };
+
+    let pairs = match left.len() <= MINIMAL_RECOVERY_LIMIT {
+        true => best_matching(&left.iter().map(|i| tokens[*i].1).collect::<Vec<_>>()),
+        false => innermost_matching(&left.iter().map(|i| tokens[*i].1).collect::<Vec<_>>()),
+    };
+    for (open, close) in pairs {
+        partner[left[open]] = Some(left[close]);
+        partner[left[close]] = Some(left[open]);
+    }
+    partner
+}
+
+/// Pairs up as many open and close tokens as possible, with pairs nested properly.
+/// Among equally good pairings, open tokens are paired with the innermost close token
+/// like a stack would.
+fn best_matching(tokens: &[Token]) -> Vec<(usize, usize)> {
+    let n = tokens.len();
+    // unpaired[i][j] is the least number of unpaired tokens in tokens[i..j]
+    let mut unpaired = vec![vec![0u32; n + 1]; n + 1];
+    for len in 1..=n {
+        for i in 0..=n - len {
+            let j = i + len;
+            unpaired[i][j] = 1 + unpaired[i + 1][j];
+            if let Ok(close) = tokens[i].matching_close() {
+                for k in i + 1..j {
+                    let cost = unpaired[i + 1][k] + unpaired[k + 1][j];
+                    if tokens[k] == close && cost < unpaired[i][j] {
+                        unpaired[i][j] = cost;
+                    }
                 }
-            } else {
-                unreachable!("illegal line, filter with syntax checker");
             }
         }
-        stack
+    }
+
+    // retrace the choices, preferring to leave the first token unpaired like above
+    let mut pairs = Vec::new();
+    let mut ranges = vec![(0, n)];
+    while let Some((i, j)) = ranges.pop() {
+        if i >= j {
+            continue;
+        }
+        if unpaired[i][j] == 1 + unpaired[i + 1][j] {
+            ranges.push((i + 1, j));
+            continue;
+        }
+        let close = tokens[i].matching_close().unwrap();
+        let k = (i + 1..j)
+            .find(|k| {
+                tokens[*k] == close && unpaired[i + 1][*k] + unpaired[k + 1][j] == unpaired[i][j]
+            })
+            .unwrap();
+        pairs.push((i, k));
+        ranges.push((i + 1, k));
+        ranges.push((k + 1, j));
+    }
+    pairs
+}
+
+/// Pairs every close token with the innermost open token it matches, dropping the
+/// open tokens above it.
+fn innermost_matching(tokens: &[Token]) -> Vec<(usize, usize)> {
+    let mut pairs = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if token.is_open() {
+            stack.push(i);
+        } else if let Some(depth) = stack
             .iter()
-            .rev()
-            .map(|token| token.matching_close().unwrap().to_string())
-            .collect()
-    }
-
-    fn completion_score(s: &str) -> usize {
-        s.chars().fold(0, |mut acc, c| {
-            let cval = match c {
-                ')' => 1,
-                ']' => 2,
-                '}' => 3,
-                '>' => 4,
-                _ => unreachable!("auto complete only has close tokens"),
-            };
-            acc = acc * 5 + cval;
-            acc
-        })
+            .rposition(|open| tokens[*open].matching_close() == Ok(*token))
+        {
+            pairs.push((stack[depth], i));
+            stack.truncate(depth);
+        }
+    }
+    pairs
+}
+
+/// A single change to a line, at the column of the token it comes before.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Edit<'a> {
+    Delete { column: usize, token: Token<'a> },
+    Insert { column: usize, token: Token<'a> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Recovery<'a> {
+    errors: Vec<SyntaxError<'a>>,
+    /// in the order of the line
+    edits: Vec<Edit<'a>>,
+}
+
+impl Recovery<'_> {
+    /// The line `s` with all edits made.
+    fn apply(&self, s: &str) -> String {
+        let mut fixed = String::new();
+        let mut edits = self.edits.iter().peekable();
+        let mut skip = 0;
+        for (column, c) in (1..).zip(s.chars().chain(std::iter::once('\n'))) {
+            while let Some(edit) = edits.peek() {
+                match **edit {
+                    Edit::Insert { column: at, token } if at == column => {
+                        fixed.push_str(token.text())
+                    }
+                    Edit::Delete { column: at, token } if at == column => skip = token.width(),
+                    _ => break,
+                }
+                edits.next();
+            }
+            match skip {
+                0 if c != '\n' => fixed.push(c),
+                0 => (),
+                _ => skip -= 1,
+            }
+        }
+        fixed
     }
 }
 
+fn completion_score(completion: &[Token]) -> usize {
+    completion
+        .iter()
+        .fold(0, |acc, token| acc * 5 + token.delimiter().completion)
+}
+
 fn main() {
-    let file = load_file("input/10.txt");
-    let errors = file.lines().filter_map(|s| SyntaxChecker::check_line(s));
-    let err_score = errors.map(|error| error.points()).sum::<usize>();
+    // cargo run --bin 10 -- check [file]
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("check") {
+        let file = load_file(args.get(2).map_or("input/10.txt", String::as_str));
+        let delimiters = DelimiterSet::default();
+        for (i, s) in file.lines().enumerate() {
+            match SyntaxChecker::recover_line(&delimiters, i + 1, s) {
+                Ok(recovery) if recovery.errors.is_empty() => (),
+                Ok(recovery) => {
+                    for error in &recovery.errors {
+                        println!("{}\n", error.render(s));
+                    }
+                    let edits = recovery.edits.len();
+                    println!("help: {} edits make it {}\n", edits, recovery.apply(s));
+                }
+                Err(error) => println!("{}\n", error.render(s)),
+            }
+        }
+        return;
+    }
+
+    let file = BufReader::new(File::open("input/10.txt").unwrap());
+    let delimiters = DelimiterSet::default();
+    let mut err_score = 0;
+    let mut ac_scores = Vec::new();
+    for analysis in SyntaxChecker::analyse_reader(&delimiters, file) {
+        match analysis.unwrap() {
+            Analysis::Valid => (),
+            Analysis::Corrupted(error) => err_score += error.points(),
+            Analysis::Incomplete(completion) => ac_scores.push(completion_score(&completion)),
+        }
+    }
     println!("part 1: {}", err_score);
 
-    let autocompletions = file
-        .lines()
-        .filter(|s| SyntaxChecker::check_line(s).is_none())
-        .map(|s| AutoComplete::check_line(s));
-    let ac_scores = autocompletions
-        .map(|s| AutoComplete::completion_score(s.as_str()))
-        .sorted()
-        .collect::<Vec<_>>();
+    ac_scores.sort_unstable();
     let ac_winner = ac_scores[ac_scores.len() / 2];
     println!("part 2: {}", ac_winner);
 }
@@ -206,10 +542,26 @@ fn load_file(path: impl AsRef<Path>) -> String {
 mod problem10 {
     use super::*;
 
+    /// The first corrupted token of the line, if any.
+    fn check_line<'a>(
+        delimiters: &'a DelimiterSet,
+        line: usize,
+        s: &str,
+    ) -> Option<SyntaxError<'a>> {
+        match SyntaxChecker::analyse_line(delimiters, line, s) {
+            Analysis::Corrupted(error) => Some(error),
+            _ => None,
+        }
+    }
+
     #[test]
     fn part1() {
         let file = load_file("input/10.test.txt");
-        let errors = file.lines().filter_map(|s| SyntaxChecker::check_line(s));
+        let delimiters = DelimiterSet::default();
+        let errors = file
+            .lines()
+            .enumerate()
+            .filter_map(|(i, s)| check_line(&delimiters, i + 1, s));
         let score = errors.map(|error| error.points()).sum::<usize>();
         assert_eq!(26397, score);
     }
@@ -217,15 +569,158 @@ mod problem10 {
     #[test]
     fn part2() {
         let file = load_file("input/10.test.txt");
-        let autocompletions = file
-            .lines()
-            .filter(|s| SyntaxChecker::check_line(s).is_none())
-            .map(|s| AutoComplete::check_line(s));
-        let ac_scores = autocompletions
-            .map(|s| AutoComplete::completion_score(s.as_str()))
+        let delimiters = DelimiterSet::default();
+        let ac_scores = SyntaxChecker::analyse_reader(&delimiters, file.as_bytes())
+            .filter_map(|analysis| match analysis.unwrap() {
+                Analysis::Incomplete(completion) => Some(completion_score(&completion)),
+                _ => None,
+            })
             .sorted()
             .collect::<Vec<_>>();
         let ac_winner = ac_scores[ac_scores.len() / 2];
         assert_eq!(288957, ac_winner);
     }
+
+    #[test]
+    fn diagnostics() {
+        let file = load_file("input/10.test.txt");
+        let delimiters = DelimiterSet::default();
+        let s = file.lines().nth(2).unwrap();
+        let error = check_line(&delimiters, 3, s).unwrap();
+        assert_eq!((error.line, error.column), (3, 13));
+        assert_eq!(
+            error.render(s),
+            "\
+error: Expected ], but found }
+ --> 3:13
+  |
+3 | {([(<{}[<>[]}>{[]{[(<()>
+  |        -    ^"
+        );
+
+        let error = check_line(&delimiters, 12, "[(a)]").unwrap();
+        assert_eq!(error.kind, ErrorKind::UnknownChar('a'));
+        assert_eq!(error.column, 3);
+        assert_eq!(error.points(), 0);
+        assert!(error.render("[(a)]").ends_with("12 | [(a)]\n   |   ^"));
+    }
+
+    #[test]
+    fn delimiter_sets() {
+        let delimiters = DelimiterSet::new(vec![
+            Delimiter::new("begin", "end", 100, 3),
+            Delimiter::new("/*", "*/", 10, 2),
+            Delimiter::new("(", ")", 1, 1),
+        ])
+        .unwrap();
+
+        let s = "begin (/* ( */ ) end";
+        assert!(check_line(&delimiters, 1, "begin ( /* */ ) end").is_none());
+        let error = check_line(&delimiters, 1, s).unwrap();
+        assert_eq!(error.column, 13);
+        assert_eq!(error.points(), 10);
+        assert_eq!(error.to_string(), "Expected ), but found */");
+        assert!(error
+            .render(s)
+            .ends_with("1 | begin (/* ( */ ) end\n  |           - ^^"));
+
+        let analysis = SyntaxChecker::analyse_line(&delimiters, 1, "begin (/* begin");
+        let completion = match analysis {
+            Analysis::Incomplete(completion) => completion,
+            _ => panic!("line should be incomplete"),
+        };
+        assert_eq!(completion.iter().join(" "), "end */ ) end");
+        assert_eq!(completion_score(&completion), 3 * 125 + 2 * 25 + 5 + 3);
+
+        assert!(DelimiterSet::new(vec![Delimiter::new("", ")", 1, 1)]).is_err());
+        assert!(DelimiterSet::new(vec![Delimiter::new("|", "|", 1, 1),]).is_err());
+    }
+
+    #[test]
+    fn recovery() {
+        let file = load_file("input/10.test.txt");
+        let delimiters = DelimiterSet::default();
+        for (i, s) in file.lines().enumerate() {
+            let recovery = SyntaxChecker::recover_line(&delimiters, i + 1, s).unwrap();
+            let first = check_line(&delimiters, i + 1, s);
+            assert_eq!(recovery.errors.first().copied(), first);
+            let fixed = recovery.apply(s);
+            let analysis = SyntaxChecker::analyse_line(&delimiters, i + 1, &fixed);
+            assert_eq!(analysis, Analysis::Valid);
+        }
+
+        // a stray closer is deleted, a missing one inserted
+        let s = "[(>)(]";
+        let recovery = SyntaxChecker::recover_line(&delimiters, 1, s).unwrap();
+        let found = recovery
+            .errors
+            .iter()
+            .map(|e| (e.column, e.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            vec![
+                (3, String::from("Expected ), but found >")),
+                (6, String::from("Expected ), but found ]")),
+            ]
+        );
+        assert_eq!(recovery.edits.len(), 2);
+        assert_eq!(recovery.apply(s), "[()()]");
+        // one stray close token, rather than closing everything it skips over
+        let s = "[((((((]))))))";
+        let recovery = SyntaxChecker::recover_line(&delimiters, 1, s).unwrap();
+        assert_eq!(recovery.errors.len(), 1);
+        assert_eq!(recovery.edits.len(), 2);
+        assert_eq!(recovery.apply(s), "[(((((())))))]");
+
+        // closing everything up to a matching open token would take 6 edits for the first
+        for (s, fewest) in [
+            ("[<(((>)))]", 2),
+            ("{([(<{}[<>[]}>{[]{[(<()>", 10),
+            ("([)]", 2),
+            ("(()]())", 1),
+        ] {
+            let recovery = SyntaxChecker::recover_line(&delimiters, 1, s).unwrap();
+            assert_eq!(recovery.edits.len(), fewest, "{}", s);
+            let fixed = recovery.apply(s);
+            let analysis = SyntaxChecker::analyse_line(&delimiters, 1, &fixed);
+            assert_eq!(analysis, Analysis::Valid);
+        }
+
+        // too long for the fewest edits, but still fixed in linear time
+        let s = "(".repeat(50_000) + "]" + &")".repeat(10_000);
+        let recovery = SyntaxChecker::recover_line(&delimiters, 1, &s).unwrap();
+        assert_eq!(recovery.errors.len(), 1);
+        assert_eq!(recovery.edits.len(), 1 + 40_000);
+        let fixed = recovery.apply(&s);
+        assert_eq!(fixed, "(".repeat(50_000) + &")".repeat(50_000));
+    }
+
+    #[test]
+    fn single_pass() {
+        let delimiters = DelimiterSet::default();
+        let text = "[<>({}){}[([])<>]]\n[({(<(())[]>[[{[]{<()<>>\n{([(<{}[<>[]}>{[]{[(<()>\n";
+        let analyses = SyntaxChecker::analyse_reader(&delimiters, text.as_bytes())
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(analyses.len(), 3);
+        assert_eq!(analyses[0], Analysis::Valid);
+        match &analyses[1] {
+            Analysis::Incomplete(completion) => {
+                assert_eq!(completion.iter().join(""), "}}]])})]");
+                assert_eq!(completion_score(completion), 288957);
+            }
+            analysis => panic!("expected an incomplete line, got {:?}", analysis),
+        }
+        match &analyses[2] {
+            Analysis::Corrupted(error) => assert_eq!((error.line, error.points()), (3, 1197)),
+            analysis => panic!("expected a corrupted line, got {:?}", analysis),
+        }
+
+        let bytes: &[u8] = b"()\n\xff\n";
+        let mut analyses = SyntaxChecker::analyse_reader(&delimiters, bytes);
+        assert_eq!(analyses.next().unwrap().unwrap(), Analysis::Valid);
+        let error = analyses.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
 }
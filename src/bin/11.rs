@@ -1,4 +1,10 @@
-use std::{collections::HashSet, path::Path};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+use itertools::Itertools;
 
 type EnergyLevel = u32;
 type OctopusCavern = Vec<Vec<EnergyLevel>>;
@@ -7,6 +13,13 @@ trait Energy {
     fn increase(&mut self);
     fn reset(&mut self);
     fn is_flashing(&self) -> bool;
+
+    /// Increases the energy, true if that makes the octopus start flashing.
+    fn charge(&mut self) -> bool {
+        let was_flashing = self.is_flashing();
+        self.increase();
+        !was_flashing && self.is_flashing()
+    }
 }
 
 impl Energy for EnergyLevel {
@@ -23,6 +36,7 @@ impl Energy for EnergyLevel {
 
 trait Cavern {
     fn from_str(s: &str) -> Self;
+    #[allow(dead_code)]
     fn debug_print(&self);
 }
 
@@ -44,24 +58,8 @@ impl Cavern for OctopusCavern {
     }
 }
 
-fn find_fresh_flashing_octopus(
-    cavern: &[Vec<u32>],
-    processed: &HashSet<(usize, usize)>,
-) -> Option<(usize, usize)> {
-    cavern
-        .iter()
-        .enumerate()
-        .map(|(row_idx, row)| {
-            row.iter()
-                .enumerate()
-                .map(move |(col_dix, e)| ((row_idx, col_dix), e))
-        })
-        .flatten()
-        .find(|(pos, e)| e.is_flashing() && !processed.contains(pos))
-        .map(|(pos, _)| pos)
-}
-
-fn increase_neighbour_energy_level(pos: (usize, usize), cavern: &mut OctopusCavern) {
+/// Positions next to `pos`, including diagonals, in a cavern of `size` rows and columns.
+fn neighbours(pos: (usize, usize), size: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
     const NEIGHBOURS: [[i32; 2]; 8] = [
         [-1, 0],
         [1, 0],
@@ -72,39 +70,171 @@ fn increase_neighbour_energy_level(pos: (usize, usize), cavern: &mut OctopusCave
         [1, 1],
         [1, -1],
     ];
-    let n_rows = cavern.len() as i32;
-    let n_cols = cavern[0].len() as i32;
+    let (n_rows, n_cols) = (size.0 as i32, size.1 as i32);
 
-    NEIGHBOURS
-        .iter()
-        .filter_map(|[r, c]| {
-            let row = pos.0 as i32 + r;
-            let col = pos.1 as i32 + c;
-            match (0..n_rows).contains(&row) && (0..n_cols).contains(&col) {
-                true => Some((row as usize, col as usize)),
-                _ => None,
+    NEIGHBOURS.iter().filter_map(move |[r, c]| {
+        let row = pos.0 as i32 + r;
+        let col = pos.1 as i32 + c;
+        match (0..n_rows).contains(&row) && (0..n_cols).contains(&col) {
+            true => Some((row as usize, col as usize)),
+            _ => None,
+        }
+    })
+}
+
+/// An octopus starting to flash during a step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Flash {
+    pos: (usize, usize),
+    /// 0 for octopuses set off by the step itself, otherwise one more than the wave of
+    /// the flash that set it off
+    wave: usize,
+    /// the neighbour whose flash set it off
+    trigger: Option<(usize, usize)>,
+}
+
+fn step(cavern: &mut OctopusCavern) -> usize {
+    step_observed(cavern, |_| ())
+}
+
+/// Octopuses start flashing once, when their energy first exceeds 9. Only those are
+/// queued to pass energy on, so a step only visits the octopuses it affects. Every
+/// flash is passed to `on_flash`, wave by wave.
+fn step_observed(cavern: &mut OctopusCavern, mut on_flash: impl FnMut(Flash)) -> usize {
+    let mut queue = VecDeque::new();
+    for (row, levels) in cavern.iter_mut().enumerate() {
+        for (col, level) in levels.iter_mut().enumerate() {
+            if level.charge() {
+                queue.push_back(Flash {
+                    pos: (row, col),
+                    wave: 0,
+                    trigger: None,
+                });
             }
-        })
-        .for_each(|(row, col)| {
-            cavern[row][col] += 1;
+        }
+    }
+
+    let size = (cavern.len(), cavern.first().map_or(0, Vec::len));
+    let mut flashed = Vec::new();
+    while let Some(flash) = queue.pop_front() {
+        on_flash(flash);
+        flashed.push(flash.pos);
+        for (row, col) in neighbours(flash.pos, size) {
+            if cavern[row][col].charge() {
+                queue.push_back(Flash {
+                    pos: (row, col),
+                    wave: flash.wave + 1,
+                    trigger: Some(flash.pos),
+                });
+            }
+        }
+    }
+
+    flashed
+        .iter()
+        .for_each(|(row, col)| cavern[*row][*col].reset());
+    flashed.len()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct FlashStats {
+    flashes: usize,
+    /// most flashes in one step, and the first step it happened in
+    largest_cascade: (usize, usize),
+    /// most waves in one step
+    deepest_cascade: usize,
+    /// how often each octopus flashed
+    frequency: Vec<Vec<usize>>,
+    /// first step in which all octopuses flashed
+    first_all_flash: Option<usize>,
+}
+
+/// Statistics over the next `steps` steps, counted from 1.
+fn flash_stats(cavern: &mut OctopusCavern, steps: usize) -> FlashStats {
+    let n_octopuses = cavern.iter().flatten().count();
+    let mut stats = FlashStats {
+        flashes: 0,
+        largest_cascade: (0, 0),
+        deepest_cascade: 0,
+        frequency: vec![vec![0; cavern[0].len()]; cavern.len()],
+        first_all_flash: None,
+    };
+    for n in 1..=steps {
+        let mut waves = 0;
+        let flashes = step_observed(cavern, |flash| {
+            stats.frequency[flash.pos.0][flash.pos.1] += 1;
+            waves = waves.max(flash.wave + 1);
         });
+        stats.flashes += flashes;
+        if flashes > stats.largest_cascade.0 {
+            stats.largest_cascade = (flashes, n);
+        }
+        stats.deepest_cascade = stats.deepest_cascade.max(waves);
+        if flashes == n_octopuses && stats.first_all_flash.is_none() {
+            stats.first_all_flash = Some(n);
+        }
+    }
+    stats
 }
 
-fn step(cavern: &mut OctopusCavern) -> usize {
-    let mut flashes = 0;
-    cavern.iter_mut().flatten().for_each(|level| *level += 1);
-    let mut processed_flashes = HashSet::new();
-    while let Some((row, col)) = find_fresh_flashing_octopus(cavern, &processed_flashes) {
-        increase_neighbour_energy_level((row, col), cavern);
-        processed_flashes.insert((row, col));
-        flashes += 1;
-    }
-    cavern.iter_mut().flatten().for_each(|level| {
-        if level.is_flashing() {
-            level.reset();
+/// The cavern's states from the first one that comes back.
+#[derive(Clone, Debug, PartialEq)]
+struct Cycle {
+    /// steps before the first repeated state
+    start: usize,
+    length: usize,
+    /// total flashes after each number of steps up to `start + length`
+    flashes: Vec<usize>,
+}
+
+impl Cycle {
+    fn total_flashes(&self, steps: u64) -> u64 {
+        let (start, length) = (self.start as u64, self.length as u64);
+        if steps <= start + length {
+            return self.flashes[steps as usize] as u64;
         }
-    });
-    flashes
+        let before = self.flashes[self.start] as u64;
+        let per_cycle = self.flashes[self.start + self.length] as u64 - before;
+        let (cycles, rest) = ((steps - start) / length, (steps - start) % length);
+        let partial = self.flashes[self.start + rest as usize] as u64 - before;
+        before + cycles * per_cycle + partial
+    }
+}
+
+fn state_hash(cavern: &OctopusCavern) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    cavern.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Steps a copy of `cavern` until one of its states repeats. Octopuses only settle
+/// into a cycle once they flash in sync, so this can take a while for big caverns.
+/// Only hashes of the states are kept; the state behind a matching hash is replayed
+/// from the start to rule out a collision.
+fn find_cycle(cavern: &OctopusCavern) -> Cycle {
+    let mut current = cavern.clone();
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut flashes = vec![0];
+    loop {
+        let steps = flashes.len() - 1;
+        let earlier = seen.entry(state_hash(&current)).or_default();
+        let repeated = earlier.iter().copied().find(|start| {
+            let mut replay = cavern.clone();
+            (0..*start).for_each(|_| {
+                step(&mut replay);
+            });
+            replay == current
+        });
+        if let Some(start) = repeated {
+            return Cycle {
+                start,
+                length: steps - start,
+                flashes,
+            };
+        }
+        earlier.push(steps);
+        flashes.push(flashes[steps] + step(&mut current));
+    }
 }
 
 fn all_flash(cavern: &mut OctopusCavern) -> usize {
@@ -118,10 +248,31 @@ fn all_flash(cavern: &mut OctopusCavern) -> usize {
 
 fn main() {
     let mut cavern = cavern_levels("input/11.txt");
-    let total_flashes = (0..100)
-        .into_iter()
-        .map(|_| step(&mut cavern))
-        .sum::<usize>();
+
+    // cargo run --bin 11 -- stats [steps]
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("stats") {
+        let steps = args.get(2).map_or(1000, |s| s.parse().unwrap());
+        let stats = flash_stats(&mut cavern, steps);
+        println!("flashes: {}", stats.flashes);
+        let (flashes, n) = stats.largest_cascade;
+        println!("largest cascade: {} flashes in step {}", flashes, n);
+        println!("deepest cascade: {} waves", stats.deepest_cascade);
+        println!("first all flash: {:?}", stats.first_all_flash);
+        for row in &stats.frequency {
+            println!("{}", row.iter().map(|f| format!("{:4}", f)).join(""));
+        }
+        return;
+    }
+
+    // cargo run --bin 11 -- flashes <steps>
+    if args.get(1).map(String::as_str) == Some("flashes") {
+        let steps = args[2].parse().unwrap();
+        println!("flashes: {}", find_cycle(&cavern).total_flashes(steps));
+        return;
+    }
+
+    let total_flashes = (0..100).map(|_| step(&mut cavern)).sum::<usize>();
     println!("part 1: {}", total_flashes);
     let all_flash = all_flash(&mut cavern);
     println!("part 2: {}", all_flash + 100);
@@ -171,10 +322,7 @@ mod problem11 {
     #[test]
     fn part1() {
         let mut cavern = cavern_levels("input/11.test.txt");
-        let total_flashes = (0..100)
-            .into_iter()
-            .map(|_| step(&mut cavern))
-            .sum::<usize>();
+        let total_flashes = (0..100).map(|_| step(&mut cavern)).sum::<usize>();
         assert_eq!(1656, total_flashes);
     }
 
@@ -184,4 +332,60 @@ mod problem11 {
         let all_flash = all_flash(&mut cavern);
         assert_eq!(all_flash, 195);
     }
+
+    #[test]
+    fn large_cavern() {
+        let mut cavern: OctopusCavern = vec![vec![9; 1000]; 1000];
+        cavern[500][500] = 1;
+        assert_eq!(step(&mut cavern), 1_000_000);
+        assert!(cavern.iter().flatten().all(|level| *level == 0));
+        assert_eq!(all_flash(&mut cavern), 10);
+    }
+
+    #[test]
+    fn flash_events() {
+        let mut cavern: OctopusCavern = Cavern::from_str("11111\n19991\n19191\n19991\n11111");
+        let mut events = Vec::new();
+        assert_eq!(step_observed(&mut cavern, |flash| events.push(flash)), 9);
+        assert_eq!(events.len(), 9);
+        assert_eq!(
+            events[0],
+            Flash {
+                pos: (1, 1),
+                wave: 0,
+                trigger: None
+            }
+        );
+        let centre = events.iter().find(|f| f.pos == (2, 2)).unwrap();
+        assert_eq!((centre.wave, centre.trigger), (1, Some((3, 3))));
+        assert!(events.windows(2).all(|w| w[0].wave <= w[1].wave));
+
+        let mut cavern = cavern_levels("input/11.test.txt");
+        let stats = flash_stats(&mut cavern, 195);
+        assert_eq!(stats.first_all_flash, Some(195));
+        assert_eq!(stats.largest_cascade, (100, 195));
+        assert_eq!(
+            stats.frequency.iter().flatten().sum::<usize>(),
+            stats.flashes
+        );
+        let mut cavern = cavern_levels("input/11.test.txt");
+        assert_eq!(flash_stats(&mut cavern, 100).flashes, 1656);
+    }
+
+    #[test]
+    fn cycle() {
+        let cavern = cavern_levels("input/11.test.txt");
+        let cycle = find_cycle(&cavern);
+        assert_eq!(cycle.length, 10);
+        assert!(cycle.start <= 195);
+        assert_eq!(cycle.total_flashes(10), 204);
+        assert_eq!(cycle.total_flashes(100), 1656);
+
+        // after synchronising at step 195 all octopuses flash every 10 steps
+        let synced = cycle.total_flashes(195);
+        let steps = 10u64.pow(15);
+        let expected = synced + (steps - 195) / 10 * 100;
+        assert_eq!(cycle.total_flashes(steps), expected);
+        assert_eq!(cycle.total_flashes(196), synced);
+    }
 }